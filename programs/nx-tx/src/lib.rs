@@ -1,5 +1,7 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Burn, Mint, MintTo, Token,  Transfer, TokenAccount};
+use anchor_spl::token::{self, Burn, MintTo, Token};
+use anchor_spl::token_interface::{self, Mint, Token2022, TokenAccount, TokenInterface, TransferChecked};
+use anchor_spl::associated_token::AssociatedToken;
 
 // This is your program's public key and it will update
 // automatically when you build the project.
@@ -11,91 +13,3998 @@ declare_id!("2Dnk29pNbq4PpfELXr7okQ7JeMieRQRjY3vpU7NRXPHa");
 pub mod reserva_property {
     use super::*;
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_reservation(
         ctx: Context<CreateReservation>,
         amount: u64,
         check_in_date: i64,
+        check_out_date: i64,
+        referrer: Option<Pubkey>,
+        guests: u8,
+        max_payment: u64,
     ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            ctx.accounts.property.status == PropertyStatus::Active,
+            ErrorCode::PropertyInactive
+        );
+        ctx.accounts
+            .property
+            .check_booking_rules(now, check_in_date, check_out_date, guests)?;
+        let referrer = validate_referrer(
+            referrer,
+            ctx.accounts.approved_referrer.as_deref(),
+            &ctx.accounts.user.key(),
+        )?;
+
+        // El importe tiene que coincidir con el presupuesto calculado en la cadena
+        let quote = ctx.accounts.property.quote(
+            ctx.accounts.rate_schedule.as_deref().map(|schedule| &**schedule),
+            check_in_date,
+            check_out_date,
+        )?;
+        require!(amount == quote, ErrorCode::AmountMismatch);
+
+        // Con otro token distinto al de la propiedad se convierte al precio del oráculo
+        let mut deposit = ctx.accounts.property.security_deposit;
+        let mut payout_account = ctx.accounts.property.payout_account;
+        let mut amount = amount;
+        if ctx.accounts.mint.key() != ctx.accounts.property.mint {
+            let accepted_mint = ctx
+                .accounts
+                .accepted_mint
+                .as_ref()
+                .ok_or(ErrorCode::MintNotAccepted)?;
+            let price_feed = ctx
+                .accounts
+                .price_feed
+                .as_ref()
+                .ok_or(ErrorCode::InvalidPriceFeed)?;
+            require_keys_eq!(price_feed.key(), accepted_mint.price_feed, ErrorCode::InvalidPriceFeed);
+            let price = oracle::load_price(price_feed, &accepted_mint.feed_id)?;
+
+            let quote_decimals = ctx.accounts.property.quote_decimals;
+            amount = accepted_mint.to_tokens(&price, amount, quote_decimals, now)?;
+            deposit = accepted_mint.to_tokens(&price, deposit, quote_decimals, now)?;
+            payout_account = accepted_mint.payout_account;
+        }
+        // Límite fijado por el huésped frente a cambios de precio antes de ejecutarse
+        let total = amount
+            .checked_add(deposit)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(total <= max_payment, ErrorCode::PaymentAboveMaximum);
+
         let reservation = &mut ctx.accounts.reservation;
         reservation.user = ctx.accounts.user.key();
         reservation.amount = amount;
         reservation.check_in_date = check_in_date;
-        reservation.is_paid = false;
+        reservation.check_out_date = check_out_date;
+        reservation.property = ctx.accounts.property.key();
+        (reservation.status, reservation.approval_deadline) = ctx
+            .accounts
+            .property
+            .confirmed_status(now, ctx.accounts.property.check_in_opens_at(check_in_date));
+        reservation.released_amount = 0;
+        reservation.deposit = deposit;
+        reservation.damage_claim = 0;
+        reservation.evidence_hash = [0; 32];
+        reservation.checked_out_at = 0;
+        reservation.deposit_released = false;
+        reservation.staked_amount = 0;
+        reservation.reward_index_snapshot = 0;
+        reservation.pending_yield = 0;
+        reservation.referrer = referrer;
+        reservation.pass_mint = ctx.accounts.pass_mint.key();
+        reservation.payout_account = payout_account;
+        reservation.guests = guests;
+        reservation.is_group = false;
+        reservation.refund_tiers = ctx
+            .accounts
+            .cancellation_policy
+            .as_ref()
+            .map(|policy| policy.tiers.clone())
+            .unwrap_or_default();
+        reservation.check_in_payout_bps = ctx.accounts.property.check_in_payout_bps;
+        reservation.damage_claim_window = ctx.accounts.property.damage_claim_window;
+        reservation.no_show_payout_bps = ctx.accounts.property.no_show_payout_bps;
+        reservation.check_in_opens_at = ctx.accounts.property.check_in_opens_at(check_in_date);
+        reservation.check_in_closes_at = ctx.accounts.property.check_in_closes_at(check_in_date);
+        reservation.pass_bump = ctx.bumps.pass_mint;
+        reservation.listed = false;
+        reservation.vault_bump = ctx.bumps.escrow_vault;
+
+        // Bloquear las noches en el calendario de la propiedad
+        let calendars = calendar::stay_calendars(&ctx.accounts.calendar, &ctx.accounts.next_calendar);
+        calendar::reserve_nights(
+            &calendars,
+            &reservation.property,
+            check_in_date,
+            check_out_date,
+        )?;
+
+        // Colocar los fondos y la fianza en el escrow de la reserva
+        let received = admin::stake_tokens(
+            ctx.accounts.user_staking_account.to_account_info(),
+            ctx.accounts.escrow_vault.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            &ctx.accounts.mint,
+            &mut ctx.accounts.staking_pool,
+            reservation,
+            total,
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+        // Con comisiones de transferencia el escrow recibe menos de lo enviado;
+        // la reserva registra lo recibido y la diferencia sale del importe
+        reservation.amount = received
+            .checked_sub(reservation.deposit)
+            .ok_or(ErrorCode::DepositNotCovered)?;
+
+        // El pase de la reserva va a la wallet del huésped
+        admin::mint_reservation_pass(
+            ctx.accounts.pass_mint.to_account_info(),
+            ctx.accounts.user_pass_account.to_account_info(),
+            &reservation.key(),
+            ctx.bumps.pass_mint,
+            ctx.accounts.property.transferable,
+            ctx.accounts.pass_token_program.to_account_info(),
+        )?;
+
+        emit!(ReservationCreatedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            mint: ctx.accounts.mint.key(),
+            amount: reservation.amount,
+            deposit: reservation.deposit,
+            check_in_date,
+            check_out_date,
+            status: reservation.status,
+            referrer,
+            guests,
+            label: "reservationcreated".to_string()
+        });
+
+        Ok(())
+    }
+
+    // Reserva de grupo: el organizador fija la parte de cada huésped y la reserva
+    // no se confirma hasta que todos han aportado la suya
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_group_reservation(
+        ctx: Context<CreateGroupReservation>,
+        amount: u64,
+        check_in_date: i64,
+        check_out_date: i64,
+        referrer: Option<Pubkey>,
+        shares: Vec<GuestShareInput>,
+        funding_deadline: i64,
+        guests: u8,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            ctx.accounts.property.status == PropertyStatus::Active,
+            ErrorCode::PropertyInactive
+        );
+        ctx.accounts
+            .property
+            .check_booking_rules(now, check_in_date, check_out_date, guests)?;
+        let referrer = validate_referrer(
+            referrer,
+            ctx.accounts.approved_referrer.as_deref(),
+            &ctx.accounts.organizer.key(),
+        )?;
+        require!(
+            funding_deadline > now && funding_deadline < check_in_date,
+            ErrorCode::InvalidFundingDeadline
+        );
+
+        let quote = ctx.accounts.property.quote(
+            ctx.accounts.rate_schedule.as_deref().map(|schedule| &**schedule),
+            check_in_date,
+            check_out_date,
+        )?;
+        require!(amount == quote, ErrorCode::AmountMismatch);
+
+        // Las partes tienen que cubrir exactamente el importe y la fianza
+        let deposit = ctx.accounts.property.security_deposit;
+        let total = amount.checked_add(deposit).ok_or(ErrorCode::MathOverflow)?;
+        validate_group_shares(&shares, total)?;
+        // Cada huésped que paga una parte cuenta como huésped de la estancia
+        require!(guests as usize >= shares.len(), ErrorCode::InvalidGuestCount);
+
+        let reservation = &mut ctx.accounts.reservation;
+        reservation.user = ctx.accounts.organizer.key();
+        reservation.amount = amount;
+        reservation.check_in_date = check_in_date;
+        reservation.check_out_date = check_out_date;
+        reservation.property = ctx.accounts.property.key();
+        reservation.status = ReservationStatus::Funding;
+        reservation.approval_deadline = 0;
+        reservation.released_amount = 0;
+        reservation.deposit = deposit;
+        reservation.damage_claim = 0;
+        reservation.evidence_hash = [0; 32];
+        reservation.checked_out_at = 0;
+        reservation.deposit_released = false;
+        reservation.staked_amount = 0;
+        reservation.reward_index_snapshot = 0;
+        reservation.pending_yield = 0;
+        reservation.referrer = referrer;
+        reservation.pass_mint = ctx.accounts.pass_mint.key();
+        reservation.payout_account = ctx.accounts.property.payout_account;
+        reservation.guests = guests;
+        reservation.is_group = true;
+        reservation.refund_tiers = ctx
+            .accounts
+            .cancellation_policy
+            .as_ref()
+            .map(|policy| policy.tiers.clone())
+            .unwrap_or_default();
+        reservation.check_in_payout_bps = ctx.accounts.property.check_in_payout_bps;
+        reservation.damage_claim_window = ctx.accounts.property.damage_claim_window;
+        reservation.no_show_payout_bps = ctx.accounts.property.no_show_payout_bps;
+        reservation.check_in_opens_at = ctx.accounts.property.check_in_opens_at(check_in_date);
+        reservation.check_in_closes_at = ctx.accounts.property.check_in_closes_at(check_in_date);
+        reservation.pass_bump = ctx.bumps.pass_mint;
+        reservation.listed = false;
+        reservation.vault_bump = ctx.bumps.escrow_vault;
+
+        // Las noches quedan retenidas mientras se reúnen las aportaciones
+        let calendars = calendar::stay_calendars(&ctx.accounts.calendar, &ctx.accounts.next_calendar);
+        calendar::reserve_nights(
+            &calendars,
+            &reservation.property,
+            check_in_date,
+            check_out_date,
+        )?;
+
+        let group_booking = &mut ctx.accounts.group_booking;
+        group_booking.reservation = reservation.key();
+        group_booking.organizer = reservation.user;
+        group_booking.funding_deadline = funding_deadline;
+        group_booking.funded_amount = 0;
+        group_booking.shares = shares
+            .iter()
+            .map(|share| GuestShare {
+                guest: share.guest,
+                amount: share.amount,
+                paid: false,
+                received: 0,
+            })
+            .collect();
+        group_booking.bump = ctx.bumps.group_booking;
+
+        admin::mint_reservation_pass(
+            ctx.accounts.pass_mint.to_account_info(),
+            ctx.accounts.organizer_pass_account.to_account_info(),
+            &reservation.key(),
+            ctx.bumps.pass_mint,
+            ctx.accounts.property.transferable,
+            ctx.accounts.pass_token_program.to_account_info(),
+        )?;
+
+        emit!(ReservationCreatedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            mint: ctx.accounts.mint.key(),
+            amount,
+            deposit,
+            check_in_date,
+            check_out_date,
+            status: reservation.status,
+            referrer,
+            guests,
+            label: "reservationcreated".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn contribute_to_reservation(ctx: Context<ContributeToReservation>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let reservation = &mut ctx.accounts.reservation;
+        require!(
+            reservation.status == ReservationStatus::Funding,
+            ErrorCode::InvalidReservationStatus
+        );
+        let group_booking = &mut ctx.accounts.group_booking;
+        require!(now <= group_booking.funding_deadline, ErrorCode::FundingDeadlinePassed);
+
+        let guest = ctx.accounts.guest.key();
+        let share = group_booking
+            .shares
+            .iter_mut()
+            .find(|share| share.guest == guest)
+            .ok_or(ErrorCode::NotGroupGuest)?;
+        require!(!share.paid, ErrorCode::ShareAlreadyPaid);
+
+        // Se guarda lo que llega al escrow para poder devolverlo si no se completa
+        let contribution = admin::transfer_in(
+            ctx.accounts.guest_token_account.to_account_info(),
+            ctx.accounts.escrow_vault.to_account_info(),
+            ctx.accounts.guest.to_account_info(),
+            &ctx.accounts.mint,
+            share.amount,
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+        share.paid = true;
+        share.received = contribution;
+
+        group_booking.funded_amount = group_booking
+            .funded_amount
+            .checked_add(contribution)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        emit!(GroupContributionEvent {
+            reservation: reservation.key(),
+            guest,
+            amount: contribution,
+            funded_amount: group_booking.funded_amount,
+            label: "groupcontribution".to_string()
+        });
+
+        // Con la última aportación la reserva se confirma y los fondos entran en staking
+        if group_booking.shares.iter().all(|share| share.paid) {
+            let funded_amount = group_booking.funded_amount;
+            reservation.amount = funded_amount
+                .checked_sub(reservation.deposit)
+                .ok_or(ErrorCode::DepositNotCovered)?;
+            staking::stake(&mut ctx.accounts.staking_pool, reservation, funded_amount)?;
+            (reservation.status, reservation.approval_deadline) =
+                ctx.accounts.property.confirmed_status(now, reservation.check_in_opens_at);
+
+            emit!(GroupBookingFundedEvent {
+                reservation: reservation.key(),
+                organizer: reservation.user,
+                funded_amount,
+                status: reservation.status,
+                label: "groupfunded".to_string()
+            });
+        }
+
+        Ok(())
+    }
+
+    // Pasado el plazo sin financiar, cualquiera puede devolver las aportaciones;
+    // `remaining_accounts` lleva la cuenta de token de cada huésped que ya pagó, en orden
+    pub fn expire_group_reservation<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExpireGroupReservation<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let reservation = &mut ctx.accounts.reservation;
+        require!(
+            reservation.status == ReservationStatus::Funding,
+            ErrorCode::InvalidReservationStatus
+        );
+        let group_booking = &ctx.accounts.group_booking;
+        require!(now > group_booking.funding_deadline, ErrorCode::FundingDeadlineOpen);
+
+        let paid_shares: Vec<&GuestShare> =
+            group_booking.shares.iter().filter(|share| share.paid).collect();
+        require!(
+            ctx.remaining_accounts.len() == paid_shares.len(),
+            ErrorCode::InvalidRemainingAccounts
+        );
+
+        for (share, token_info) in paid_shares.iter().zip(ctx.remaining_accounts.iter()) {
+            let guest_token_account = InterfaceAccount::<TokenAccount>::try_from(token_info)?;
+            require!(
+                guest_token_account.owner == share.guest
+                    && guest_token_account.mint == ctx.accounts.escrow_vault.mint,
+                ErrorCode::InvalidRemainingAccounts
+            );
+
+            admin::transfer_from_escrow(
+                ctx.accounts.escrow_vault.to_account_info(),
+                token_info.clone(),
+                &ctx.accounts.mint,
+                &reservation.key(),
+                reservation.vault_bump,
+                share.received,
+                ctx.accounts.token_program.to_account_info(),
+            )?;
+        }
+
+        let calendars = calendar::stay_calendars(&ctx.accounts.calendar, &ctx.accounts.next_calendar);
+        calendar::release_nights(
+            &calendars,
+            &reservation.property,
+            reservation.check_in_date,
+            reservation.check_out_date,
+        )?;
+
+        reservation.deposit_released = true;
+        reservation.status = ReservationStatus::Expired;
+
+        emit!(ReservationRejectedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            refund: group_booking.funded_amount,
+            status: reservation.status,
+            label: "reservationrejected".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn check_in(ctx: Context<CheckIn>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let reservation = &mut ctx.accounts.reservation;
+        // Permitir check-in dentro de la ventana fijada al reservar
+        require!(
+            now >= reservation.check_in_opens_at && now <= reservation.check_in_closes_at,
+            ErrorCode::InvalidCheckInDate
+        );
+        require!(
+            reservation.status != ReservationStatus::Cancelled,
+            ErrorCode::ReservationCancelled
+        );
+        require!(
+            reservation.status != ReservationStatus::Pending,
+            ErrorCode::ReservationPending
+        );
+        // Quien tenga el pase pasa a ser el huésped de la reserva
+        reservation.user = ctx.accounts.user.key();
+        require!(
+            reservation.status == ReservationStatus::Booked,
+            ErrorCode::AlreadyPaid
+        );
+
+        // Al llegar solo se libera al propietario la primera parte del pago
+        let payout = apply_bps(reservation.amount, reservation.check_in_payout_bps);
+        if payout > 0 {
+            ctx.accounts.settlement.pay_owner(
+                reservation,
+                &ctx.accounts.property,
+                ctx.accounts.escrow_vault.to_account_info(),
+                ctx.accounts.owner_account.to_account_info(),
+                payout,
+                ctx.accounts.token_program.to_account_info(),
+            )?;
+        }
+
+        // El periodo de staking termina con la llegada del huésped
+        ctx.accounts.settlement.settle_yield(
+            reservation,
+            &ctx.accounts.escrow_vault.mint,
+            ctx.accounts.user_staking_account.to_account_info(),
+            ctx.accounts.owner_account.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        // Actualizar el estado de la reserva
+        reservation.released_amount = payout;
+        reservation.status = ReservationStatus::CheckedIn;
+
+        // El pase se consume aquí: se quema y se cierran su mint y la cuenta del titular
+        admin::retire_reservation_pass(
+            ctx.accounts.pass_mint.to_account_info(),
+            Some(ctx.accounts.user_pass_account.to_account_info()),
+            ctx.accounts.user.to_account_info(),
+            &reservation.key(),
+            reservation.pass_bump,
+            ctx.accounts.pass_token_program.to_account_info(),
+        )?;
+        admin::close_pass_account(
+            ctx.accounts.user_pass_account.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            ctx.accounts.pass_token_program.to_account_info(),
+        )?;
+
+        emit!(CheckedInEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            released_amount: payout,
+            checked_in_at: now,
+            label: "checkedin".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn check_out(ctx: Context<CheckOut>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let reservation = &mut ctx.accounts.reservation;
+        require!(
+            reservation.status == ReservationStatus::CheckedIn,
+            ErrorCode::InvalidReservationStatus
+        );
+
+        // Si no es el huésped quien confirma la salida, hay que esperar al fin del plazo de disputa
+        if ctx.accounts.authority.key() != reservation.user {
+            require!(
+                now >= reservation.check_out_date.saturating_add(ctx.accounts.property.payout_delay),
+                ErrorCode::PayoutNotYetAvailable
+            );
+        }
+
+        // Liberar el resto del pago al propietario
+        let remainder = reservation.amount - reservation.released_amount;
+        if remainder > 0 {
+            ctx.accounts.settlement.pay_owner(
+                reservation,
+                &ctx.accounts.property,
+                ctx.accounts.escrow_vault.to_account_info(),
+                ctx.accounts.owner_account.to_account_info(),
+                remainder,
+                ctx.accounts.token_program.to_account_info(),
+            )?;
+        }
+
+        reservation.released_amount = reservation.amount;
+        reservation.checked_out_at = now;
+        reservation.status = ReservationStatus::CheckedOut;
+
+        emit!(CheckedOutEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            released_amount: remainder,
+            checked_out_at: now,
+            confirmed_by: ctx.accounts.authority.key(),
+            label: "checkedout".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_damages(
+        ctx: Context<ClaimDamages>,
+        amount: u64,
+        evidence_hash: [u8; 32],
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let reservation = &mut ctx.accounts.reservation;
+        require!(
+            reservation.status == ReservationStatus::CheckedOut,
+            ErrorCode::InvalidReservationStatus
+        );
+        let claim_deadline = reservation
+            .checked_out_at
+            .checked_add(reservation.damage_claim_window)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(now <= claim_deadline, ErrorCode::DamageClaimWindowClosed);
+        require!(
+            amount > 0 && amount <= reservation.deposit && reservation.damage_claim == 0,
+            ErrorCode::InvalidDamageClaim
+        );
+
+        // La reclamación se paga al cerrar el plazo, así el huésped puede disputarla
+        reservation.damage_claim = amount;
+        reservation.evidence_hash = evidence_hash;
+
+        emit!(DamagesClaimedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            amount,
+            evidence_hash,
+            label: "damagesclaimed".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn release_deposit<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ReleaseDeposit<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let reservation = &mut ctx.accounts.reservation;
+        require!(
+            reservation.status == ReservationStatus::CheckedOut,
+            ErrorCode::InvalidReservationStatus
+        );
+        require!(!reservation.deposit_released, ErrorCode::DepositAlreadyReleased);
+        let claim_deadline = reservation
+            .checked_out_at
+            .checked_add(reservation.damage_claim_window)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(now > claim_deadline, ErrorCode::DamageClaimWindowOpen);
+
+        // Pagar los daños reclamados al propietario
+        if reservation.damage_claim > 0 {
+            admin::transfer_from_escrow(
+                ctx.accounts.escrow_vault.to_account_info(),
+                ctx.accounts.owner_account.to_account_info(),
+                &ctx.accounts.mint,
+                &reservation.key(),
+                reservation.vault_bump,
+                reservation.damage_claim,
+                ctx.accounts.token_program.to_account_info(),
+            )?;
+        }
+
+        // Devolver al huésped la fianza no reclamada
+        let unclaimed = reservation.deposit - reservation.damage_claim;
+        admin::refund_guests(
+            ctx.accounts.escrow_vault.to_account_info(),
+            ctx.accounts.user_staking_account.to_account_info(),
+            ctx.accounts.group_booking.as_deref().map(|group| &**group),
+            ctx.remaining_accounts,
+            &ctx.accounts.mint,
+            reservation,
+            unclaimed,
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        reservation.deposit_released = true;
+
+        emit!(DepositReleasedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            damages: reservation.damage_claim,
+            refund: unclaimed,
+            label: "depositreleased".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn init_cancellation_policy(
+        ctx: Context<InitCancellationPolicy>,
+        tiers: Vec<RefundTier>,
+    ) -> Result<()> {
+        validate_refund_tiers(&tiers)?;
+
+        let policy = &mut ctx.accounts.cancellation_policy;
+        policy.owner = ctx.accounts.owner.key();
+        policy.property = ctx.accounts.property.key();
+        policy.tiers = tiers;
+        policy.bump = ctx.bumps.cancellation_policy;
+        Ok(())
+    }
+
+    pub fn update_cancellation_policy(
+        ctx: Context<UpdateCancellationPolicy>,
+        tiers: Vec<RefundTier>,
+    ) -> Result<()> {
+        validate_refund_tiers(&tiers)?;
+
+        ctx.accounts.cancellation_policy.tiers = tiers;
+        Ok(())
+    }
+
+    pub fn init_rate_schedule(
+        ctx: Context<InitRateSchedule>,
+        weekday_multipliers_bps: [u16; 7],
+        overrides: Vec<RateOverride>,
+        stay_discounts: Vec<StayDiscount>,
+    ) -> Result<()> {
+        validate_rate_schedule(&weekday_multipliers_bps, &overrides, &stay_discounts)?;
+
+        let schedule = &mut ctx.accounts.rate_schedule;
+        schedule.owner = ctx.accounts.owner.key();
+        schedule.property = ctx.accounts.property.key();
+        schedule.weekday_multipliers_bps = weekday_multipliers_bps;
+        schedule.overrides = overrides;
+        schedule.stay_discounts = stay_discounts;
+        schedule.bump = ctx.bumps.rate_schedule;
+
+        // A partir de ahora los presupuestos tienen que usar el calendario de tarifas
+        ctx.accounts.property.has_rate_schedule = true;
+        Ok(())
+    }
+
+    pub fn update_rate_schedule(
+        ctx: Context<UpdateRateSchedule>,
+        weekday_multipliers_bps: [u16; 7],
+        overrides: Vec<RateOverride>,
+        stay_discounts: Vec<StayDiscount>,
+    ) -> Result<()> {
+        validate_rate_schedule(&weekday_multipliers_bps, &overrides, &stay_discounts)?;
+
+        let schedule = &mut ctx.accounts.rate_schedule;
+        schedule.weekday_multipliers_bps = weekday_multipliers_bps;
+        schedule.overrides = overrides;
+        schedule.stay_discounts = stay_discounts;
+        Ok(())
+    }
+
+    pub fn cancel_reservation<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelReservation<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let reservation = &mut ctx.accounts.reservation;
+        reservation.user = ctx.accounts.user.key();
+        require!(
+            reservation.status != ReservationStatus::Cancelled,
+            ErrorCode::ReservationCancelled
+        );
+        require!(
+            reservation.status == ReservationStatus::Booked
+                || reservation.status == ReservationStatus::Pending,
+            ErrorCode::AlreadyPaid
+        );
+
+        // El porcentaje de reembolso depende de la antelación con la que se cancela
+        let refund_bps = reservation.refund_bps(now);
+        let refund = apply_bps(reservation.amount, refund_bps);
+        let penalty = reservation.amount - refund;
+
+        // Devolver al huésped la parte reembolsable y la fianza completa
+        admin::refund_guests(
+            ctx.accounts.escrow_vault.to_account_info(),
+            ctx.accounts.user_staking_account.to_account_info(),
+            ctx.accounts.group_booking.as_deref().map(|group| &**group),
+            ctx.remaining_accounts,
+            &ctx.accounts.settlement.mint,
+            reservation,
+            refund + reservation.deposit,
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        // El resto se le paga al propietario, descontadas las comisiones
+        if penalty > 0 {
+            ctx.accounts.settlement.pay_owner(
+                reservation,
+                &ctx.accounts.property,
+                ctx.accounts.escrow_vault.to_account_info(),
+                ctx.accounts.owner_account.to_account_info(),
+                penalty,
+                ctx.accounts.token_program.to_account_info(),
+            )?;
+        }
+
+        ctx.accounts.settlement.settle_yield(
+            reservation,
+            &ctx.accounts.escrow_vault.mint,
+            ctx.accounts.user_staking_account.to_account_info(),
+            ctx.accounts.owner_account.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        // Liberar las noches en el calendario
+        let calendars = calendar::stay_calendars(&ctx.accounts.calendar, &ctx.accounts.next_calendar);
+        calendar::release_nights(
+            &calendars,
+            &reservation.property,
+            reservation.check_in_date,
+            reservation.check_out_date,
+        )?;
+
+        reservation.deposit_released = true;
+        reservation.status = ReservationStatus::Cancelled;
+
+        // El pase se consume aquí: se quema y se cierran su mint y la cuenta del titular
+        admin::retire_reservation_pass(
+            ctx.accounts.pass_mint.to_account_info(),
+            Some(ctx.accounts.user_pass_account.to_account_info()),
+            ctx.accounts.user.to_account_info(),
+            &reservation.key(),
+            reservation.pass_bump,
+            ctx.accounts.pass_token_program.to_account_info(),
+        )?;
+        admin::close_pass_account(
+            ctx.accounts.user_pass_account.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            ctx.accounts.pass_token_program.to_account_info(),
+        )?;
+
+        emit!(ReservationCancelledEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            refund,
+            penalty,
+            deposit_refund: reservation.deposit,
+            cancelled_at: now,
+            label: "reservationcancelled".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn approve_reservation(ctx: Context<ApproveReservation>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.property.authorize(
+            &ctx.accounts.authority.key(),
+            ctx.accounts.role.as_deref(),
+            Permission::ApproveBookings,
+        )?;
+
+        let reservation = &mut ctx.accounts.reservation;
+        require!(
+            reservation.status == ReservationStatus::Pending,
+            ErrorCode::InvalidReservationStatus
+        );
+        require!(now <= reservation.approval_deadline, ErrorCode::RequestExpired);
+
+        reservation.status = ReservationStatus::Booked;
+
+        emit!(ReservationApprovedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            label: "reservationapproved".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn decline_reservation<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RejectReservation<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.property.authorize(
+            &ctx.accounts.authority.key(),
+            ctx.accounts.role.as_deref(),
+            Permission::ApproveBookings,
+        )?;
+
+        ctx.accounts
+            .refund_request(ctx.remaining_accounts, ReservationStatus::Declined)
+    }
+
+    // Cualquiera puede caducar una solicitud que el propietario no ha respondido a tiempo
+    pub fn expire_reservation<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RejectReservation<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now > ctx.accounts.reservation.approval_deadline,
+            ErrorCode::RequestNotExpired
+        );
+
+        ctx.accounts
+            .refund_request(ctx.remaining_accounts, ReservationStatus::Expired)
+    }
+
+    pub fn modify_reservation(
+        ctx: Context<ModifyReservation>,
+        check_in_date: i64,
+        check_out_date: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let property = &ctx.accounts.property;
+        let reservation = &mut ctx.accounts.reservation;
+        reservation.user = ctx.accounts.user.key();
+        require!(
+            reservation.status == ReservationStatus::Booked,
+            ErrorCode::InvalidReservationStatus
+        );
+        // Las reservas pagadas con otro token quedan fijadas al cambio del día de la reserva
+        // Las reservas de grupo tampoco, porque la diferencia no se reparte entre los huéspedes
+        // Con solicitud de reserva las fechas nuevas no estarían aprobadas por el propietario
+        require!(
+            property.allow_modifications
+                && property.booking_mode == BookingMode::Instant
+                && !reservation.is_group
+                && ctx.accounts.escrow_vault.mint == property.mint
+                && now.saturating_add(property.modification_cutoff) <= reservation.check_in_date,
+            ErrorCode::ModificationNotAllowed
+        );
+        property.check_booking_rules(now, check_in_date, check_out_date, reservation.guests)?;
+
+        let quote = property.quote(
+            ctx.accounts.rate_schedule.as_deref().map(|schedule| &**schedule),
+            check_in_date,
+            check_out_date,
+        )?;
+
+        // Mover las noches: primero se liberan las antiguas para permitir solapes
+        let calendars = calendar::stay_calendars(&ctx.accounts.calendar, &ctx.accounts.next_calendar);
+        calendar::release_nights(
+            &calendars,
+            &reservation.property,
+            reservation.check_in_date,
+            reservation.check_out_date,
+        )?;
+        calendar::reserve_nights(&calendars, &reservation.property, check_in_date, check_out_date)?;
+
+        // Cobrar o devolver la diferencia de precio contra el escrow
+        let mut new_amount = quote;
+        if quote > reservation.amount {
+            let received = admin::transfer_in(
+                ctx.accounts.user_staking_account.to_account_info(),
+                ctx.accounts.escrow_vault.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.mint,
+                quote - reservation.amount,
+                ctx.accounts.token_program.to_account_info(),
+            )?;
+            new_amount = reservation.amount + received;
+        } else if quote < reservation.amount {
+            admin::transfer_from_escrow(
+                ctx.accounts.escrow_vault.to_account_info(),
+                ctx.accounts.user_staking_account.to_account_info(),
+                &ctx.accounts.mint,
+                &reservation.key(),
+                reservation.vault_bump,
+                reservation.amount - quote,
+                ctx.accounts.token_program.to_account_info(),
+            )?;
+        }
+
+        // Ajustar lo que hay en staking sin perder el rendimiento ya generado
+        let staked = new_amount
+            .checked_add(reservation.deposit)
+            .ok_or(ErrorCode::MathOverflow)?;
+        staking::restake(&mut ctx.accounts.staking_pool, reservation, staked)?;
+
+        emit!(ReservationModifiedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            old_amount: reservation.amount,
+            new_amount,
+            old_check_in_date: reservation.check_in_date,
+            old_check_out_date: reservation.check_out_date,
+            new_check_in_date: check_in_date,
+            new_check_out_date: check_out_date,
+            label: "reservationmodified".to_string()
+        });
+
+        reservation.amount = new_amount;
+        // La ventana de check-in se desplaza con las fechas
+        reservation.check_in_opens_at = check_in_date
+            .saturating_add(reservation.check_in_opens_at - reservation.check_in_date);
+        reservation.check_in_closes_at = check_in_date
+            .saturating_add(reservation.check_in_closes_at - reservation.check_in_date);
+        reservation.check_in_date = check_in_date;
+        reservation.check_out_date = check_out_date;
+        Ok(())
+    }
+
+    pub fn register_property(
+        ctx: Context<RegisterProperty>,
+        property_id: u64,
+        nightly_rate: u64,
+        cleaning_fee: u64,
+    ) -> Result<()> {
+        require!(nightly_rate > 0, ErrorCode::InvalidPropertyConfig);
+
+        let property = &mut ctx.accounts.property;
+        property.owner = ctx.accounts.owner.key();
+        property.property_id = property_id;
+        property.payout_account = ctx.accounts.payout_account.key();
+        property.mint = ctx.accounts.mint.key();
+        property.quote_decimals = ctx.accounts.mint.decimals;
+        property.nightly_rate = nightly_rate;
+        property.cleaning_fee = cleaning_fee;
+        property.has_rate_schedule = false;
+        property.check_in_earliest_offset = 0;
+        property.check_in_latest_offset = DEFAULT_CHECK_IN_LATEST_OFFSET;
+        property.utc_offset = 0;
+        property.check_in_payout_bps = DEFAULT_CHECK_IN_PAYOUT_BPS;
+        property.payout_delay = DEFAULT_PAYOUT_DELAY;
+        property.security_deposit = 0;
+        property.damage_claim_window = DEFAULT_DAMAGE_CLAIM_WINDOW;
+        property.no_show_payout_bps = BPS_DENOMINATOR as u16;
+        property.allow_modifications = true;
+        property.modification_cutoff = DEFAULT_MODIFICATION_CUTOFF;
+        property.booking_mode = BookingMode::Instant;
+        property.approval_timeout = DEFAULT_APPROVAL_TIMEOUT;
+        property.transferable = false;
+        property.resale_royalty_bps = 0;
+        property.resale_price_cap_bps = BPS_DENOMINATOR as u16;
+        property.delegate = Pubkey::default();
+        property.delegate_payout_account = Pubkey::default();
+        property.delegate_payout_bps = 0;
+        property.min_nights = DEFAULT_MIN_NIGHTS;
+        property.max_nights = DEFAULT_MAX_NIGHTS;
+        property.max_guests = DEFAULT_MAX_GUESTS;
+        property.min_lead_time = 0;
+        property.max_booking_horizon = DEFAULT_MAX_BOOKING_HORIZON;
+        property.status = PropertyStatus::Active;
+        property.bump = ctx.bumps.property;
+        Ok(())
+    }
+
+    pub fn update_property(
+        ctx: Context<UpdateProperty>,
+        nightly_rate: u64,
+        cleaning_fee: u64,
+        status: PropertyStatus,
+    ) -> Result<()> {
+        require!(nightly_rate > 0, ErrorCode::InvalidPropertyConfig);
+
+        let property = &mut ctx.accounts.property;
+        property.payout_account = ctx.accounts.payout_account.key();
+        property.nightly_rate = nightly_rate;
+        property.cleaning_fee = cleaning_fee;
+        property.status = status;
+        Ok(())
+    }
+
+    pub fn set_check_in_policy(
+        ctx: Context<ConfigureProperty>,
+        earliest_offset: i64,
+        latest_offset: i64,
+        utc_offset: i32,
+    ) -> Result<()> {
+        require!(
+            -MAX_CHECK_IN_OFFSET <= earliest_offset
+                && earliest_offset < latest_offset
+                && latest_offset > 0
+                && latest_offset <= MAX_CHECK_IN_OFFSET
+                && (MIN_UTC_OFFSET..=MAX_UTC_OFFSET).contains(&utc_offset),
+            ErrorCode::InvalidPropertyConfig
+        );
+
+        let property = &mut ctx.accounts.property;
+        property.check_in_earliest_offset = earliest_offset;
+        property.check_in_latest_offset = latest_offset;
+        property.utc_offset = utc_offset;
+        Ok(())
+    }
+
+    pub fn set_booking_rules(
+        ctx: Context<ConfigureProperty>,
+        min_nights: u16,
+        max_nights: u16,
+        max_guests: u8,
+        min_lead_time: i64,
+        max_booking_horizon: i64,
+    ) -> Result<()> {
+        require!(
+            min_nights > 0
+                && min_nights <= max_nights
+                && max_guests > 0
+                && min_lead_time >= 0
+                && min_lead_time < max_booking_horizon,
+            ErrorCode::InvalidPropertyConfig
+        );
+
+        let property = &mut ctx.accounts.property;
+        property.min_nights = min_nights;
+        property.max_nights = max_nights;
+        property.max_guests = max_guests;
+        property.min_lead_time = min_lead_time;
+        property.max_booking_horizon = max_booking_horizon;
+        Ok(())
+    }
+
+    pub fn set_payout_policy(
+        ctx: Context<ConfigureProperty>,
+        check_in_payout_bps: u16,
+        payout_delay: i64,
+    ) -> Result<()> {
+        require!(
+            check_in_payout_bps as u64 <= BPS_DENOMINATOR && payout_delay >= 0,
+            ErrorCode::InvalidPropertyConfig
+        );
+
+        let property = &mut ctx.accounts.property;
+        property.check_in_payout_bps = check_in_payout_bps;
+        property.payout_delay = payout_delay;
+        Ok(())
+    }
+
+    pub fn set_deposit_policy(
+        ctx: Context<ConfigureProperty>,
+        security_deposit: u64,
+        damage_claim_window: i64,
+    ) -> Result<()> {
+        require!(
+            (0..=MAX_DAMAGE_CLAIM_WINDOW).contains(&damage_claim_window),
+            ErrorCode::InvalidPropertyConfig
+        );
+
+        let property = &mut ctx.accounts.property;
+        property.security_deposit = security_deposit;
+        property.damage_claim_window = damage_claim_window;
+        Ok(())
+    }
+
+    pub fn set_no_show_policy(ctx: Context<ConfigureProperty>, no_show_payout_bps: u16) -> Result<()> {
+        require!(
+            no_show_payout_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidPropertyConfig
+        );
+
+        ctx.accounts.property.no_show_payout_bps = no_show_payout_bps;
+        Ok(())
+    }
+
+    pub fn set_modification_policy(
+        ctx: Context<ConfigureProperty>,
+        allow_modifications: bool,
+        modification_cutoff: i64,
+    ) -> Result<()> {
+        require!(modification_cutoff >= 0, ErrorCode::InvalidPropertyConfig);
+
+        let property = &mut ctx.accounts.property;
+        property.allow_modifications = allow_modifications;
+        property.modification_cutoff = modification_cutoff;
+        Ok(())
+    }
+
+    // Solo afecta a los pases emitidos a partir de ahora
+    pub fn set_transferability(ctx: Context<ConfigureProperty>, transferable: bool) -> Result<()> {
+        ctx.accounts.property.transferable = transferable;
+        Ok(())
+    }
+
+    pub fn set_resale_policy(
+        ctx: Context<ConfigureProperty>,
+        resale_royalty_bps: u16,
+        resale_price_cap_bps: u16,
+    ) -> Result<()> {
+        require!(
+            resale_royalty_bps as u64 <= BPS_DENOMINATOR && resale_price_cap_bps > 0,
+            ErrorCode::InvalidPropertyConfig
+        );
+
+        let property = &mut ctx.accounts.property;
+        property.resale_royalty_bps = resale_royalty_bps;
+        property.resale_price_cap_bps = resale_price_cap_bps;
+        Ok(())
+    }
+
+    pub fn add_accepted_mint(
+        ctx: Context<AddAcceptedMint>,
+        feed_id: [u8; 32],
+        max_price_age: i64,
+        max_confidence_bps: u16,
+    ) -> Result<()> {
+        require!(
+            max_price_age > 0 && max_confidence_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidPropertyConfig
+        );
+        require_keys_neq!(
+            ctx.accounts.mint.key(),
+            ctx.accounts.property.mint,
+            ErrorCode::InvalidMint
+        );
+        // La cuenta tiene que ser una actualización de Pyth de ese feed
+        oracle::load_price(&ctx.accounts.price_feed, &feed_id)?;
+
+        let accepted_mint = &mut ctx.accounts.accepted_mint;
+        accepted_mint.property = ctx.accounts.property.key();
+        accepted_mint.mint = ctx.accounts.mint.key();
+        accepted_mint.decimals = ctx.accounts.mint.decimals;
+        accepted_mint.price_feed = ctx.accounts.price_feed.key();
+        accepted_mint.feed_id = feed_id;
+        accepted_mint.payout_account = ctx.accounts.payout_account.key();
+        accepted_mint.max_price_age = max_price_age;
+        accepted_mint.max_confidence_bps = max_confidence_bps;
+        accepted_mint.bump = ctx.bumps.accepted_mint;
+        Ok(())
+    }
+
+    pub fn grant_role(
+        ctx: Context<GrantRole>,
+        role: Role,
+        can_approve_bookings: bool,
+        can_block_dates: bool,
+        payout_share_bps: u16,
+    ) -> Result<()> {
+        require_keys_neq!(
+            ctx.accounts.member.key(),
+            ctx.accounts.owner.key(),
+            ErrorCode::InvalidRole
+        );
+        // El personal de limpieza no gestiona reservas
+        require!(
+            !(role == Role::Cleaner && can_approve_bookings),
+            ErrorCode::InvalidRole
+        );
+        require!(
+            payout_share_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidPropertyConfig
+        );
+
+        let property = &mut ctx.accounts.property;
+        let mut payout_account = Pubkey::default();
+        if payout_share_bps > 0 {
+            // Solo un miembro puede cobrar una parte de los pagos a la vez; para cambiarlo
+            // hay que revocar antes el rol del que la cobra ahora
+            require!(property.delegate_payout_bps == 0, ErrorCode::PayoutShareTaken);
+            let account = ctx
+                .accounts
+                .payout_account
+                .as_ref()
+                .ok_or(ErrorCode::InvalidPayoutAccount)?;
+            payout_account = account.key();
+            property.delegate = ctx.accounts.member.key();
+            property.delegate_payout_account = payout_account;
+            property.delegate_payout_bps = payout_share_bps;
+        }
+
+        let property_role = &mut ctx.accounts.property_role;
+        property_role.property = property.key();
+        property_role.member = ctx.accounts.member.key();
+        property_role.role = role;
+        property_role.can_approve_bookings = can_approve_bookings;
+        property_role.can_block_dates = can_block_dates;
+        property_role.payout_share_bps = payout_share_bps;
+        property_role.payout_account = payout_account;
+        property_role.bump = ctx.bumps.property_role;
+
+        emit!(RoleGrantedEvent {
+            property: property.key(),
+            member: property_role.member,
+            role,
+            can_approve_bookings,
+            can_block_dates,
+            payout_share_bps,
+            label: "rolegranted".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn revoke_role(ctx: Context<RevokeRole>) -> Result<()> {
+        let property = &mut ctx.accounts.property;
+        let property_role = &ctx.accounts.property_role;
+        if property_role.payout_share_bps > 0 {
+            property.delegate = Pubkey::default();
+            property.delegate_payout_account = Pubkey::default();
+            property.delegate_payout_bps = 0;
+        }
+
+        emit!(RoleRevokedEvent {
+            property: property.key(),
+            member: property_role.member,
+            role: property_role.role,
+            label: "rolerevoked".to_string()
+        });
+
+        Ok(())
+    }
+
+    // Las reservas ya pagadas con ese token no se ven afectadas: guardan su importe
+    // convertido y su cuenta de cobro, y los pagos nunca vuelven a leer `AcceptedMint`
+    pub fn remove_accepted_mint(ctx: Context<RemoveAcceptedMint>) -> Result<()> {
+        emit!(AcceptedMintRemovedEvent {
+            property: ctx.accounts.property.key(),
+            mint: ctx.accounts.accepted_mint.mint,
+            label: "acceptedmintremoved".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn set_booking_mode(
+        ctx: Context<ConfigureProperty>,
+        booking_mode: BookingMode,
+        approval_timeout: i64,
+    ) -> Result<()> {
+        require!(approval_timeout > 0, ErrorCode::InvalidPropertyConfig);
+
+        let property = &mut ctx.accounts.property;
+        property.booking_mode = booking_mode;
+        property.approval_timeout = approval_timeout;
+        Ok(())
+    }
+
+    pub fn settle_no_show<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleNoShow<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let reservation = &mut ctx.accounts.reservation;
+        require!(
+            reservation.status == ReservationStatus::Booked,
+            ErrorCode::InvalidReservationStatus
+        );
+        // Solo una vez cerrado el plazo de check-in sin que el huésped se presente
+        require!(
+            now > reservation.check_in_closes_at,
+            ErrorCode::NoShowWindowOpen
+        );
+
+        require!(
+            !reservation.is_group || ctx.accounts.group_booking.is_some(),
+            ErrorCode::GroupBookingRequired
+        );
+        // Una oferta pendiente se retira con la reserva y el pase se quema en su vault
+        require!(
+            !reservation.listed
+                || (ctx.accounts.listing.is_some()
+                    && ctx.accounts.seller.is_some()
+                    && ctx.accounts.listing_pass_vault.is_some()),
+            ErrorCode::ListingRequired
+        );
+
+        let payout = apply_bps(reservation.amount, reservation.no_show_payout_bps);
+        let refund = reservation.amount - payout + reservation.deposit;
+
+        if payout > 0 {
+            ctx.accounts.settlement.pay_owner(
+                reservation,
+                &ctx.accounts.property,
+                ctx.accounts.escrow_vault.to_account_info(),
+                ctx.accounts.owner_account.to_account_info(),
+                payout,
+                ctx.accounts.token_program.to_account_info(),
+            )?;
+        }
+
+        // Devolver al huésped lo que no cubre la política y la fianza
+        admin::refund_guests(
+            ctx.accounts.escrow_vault.to_account_info(),
+            ctx.accounts.user_staking_account.to_account_info(),
+            ctx.accounts.group_booking.as_deref().map(|group| &**group),
+            ctx.remaining_accounts,
+            &ctx.accounts.settlement.mint,
+            reservation,
+            refund,
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        ctx.accounts.settlement.settle_yield(
+            reservation,
+            &ctx.accounts.escrow_vault.mint,
+            ctx.accounts.user_staking_account.to_account_info(),
+            ctx.accounts.owner_account.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        // Liberar las noches que quedan de la estancia
+        let calendars = calendar::stay_calendars(&ctx.accounts.calendar, &ctx.accounts.next_calendar);
+        calendar::release_nights(
+            &calendars,
+            &reservation.property,
+            reservation.check_in_date,
+            reservation.check_out_date,
+        )?;
+
+        emit!(NoShowSettledEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            owner_payout: payout,
+            refund,
+            label: "noshowsettled".to_string()
+        });
+
+        // Quemar el pase y cerrar su mint y el escrow; la reserva se cierra al terminar
+        // la instrucción
+        let pass_holder = if reservation.listed {
+            ctx.accounts.listing_pass_vault.as_ref()
+        } else {
+            ctx.accounts.pass_account.as_ref()
+        };
+        admin::retire_reservation_pass(
+            ctx.accounts.pass_mint.to_account_info(),
+            pass_holder.map(|account| account.to_account_info()),
+            ctx.accounts.user.to_account_info(),
+            &reservation.key(),
+            reservation.pass_bump,
+            ctx.accounts.pass_token_program.to_account_info(),
+        )?;
+        if let (Some(listing), Some(seller), Some(listing_pass_vault)) = (
+            &ctx.accounts.listing,
+            &ctx.accounts.seller,
+            &ctx.accounts.listing_pass_vault,
+        ) {
+            admin::close_listing_vault(
+                listing_pass_vault.to_account_info(),
+                listing.to_account_info(),
+                seller.to_account_info(),
+                &reservation.key(),
+                listing.bump,
+                ctx.accounts.pass_token_program.to_account_info(),
+            )?;
+
+            emit!(ListingCancelledEvent {
+                reservation: reservation.key(),
+                seller: seller.key(),
+                label: "listingcancelled".to_string()
+            });
+        }
+        admin::close_escrow(
+            ctx.accounts.escrow_vault.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            &reservation.key(),
+            reservation.vault_bump,
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        Ok(())
+    }
+
+    // El pase queda depositado en la oferta hasta que se compra o se retira
+    pub fn list_reservation(ctx: Context<ListReservation>, price: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let property = &ctx.accounts.property;
+        let reservation = &mut ctx.accounts.reservation;
+        // Los reembolsos de un grupo van a quienes pagaron, no al comprador
+        require!(
+            property.transferable && !reservation.is_group,
+            ErrorCode::ResaleNotAllowed
+        );
+        require!(
+            reservation.status == ReservationStatus::Booked,
+            ErrorCode::InvalidReservationStatus
+        );
+        // Cerrado el check-in ya no hay estancia que vender
+        require!(now < reservation.check_in_closes_at, ErrorCode::ResaleWindowClosed);
+        require!(
+            price > 0 && price <= apply_bps(reservation.amount, property.resale_price_cap_bps),
+            ErrorCode::ResalePriceTooHigh
+        );
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.seller_pass_account.to_account_info(),
+            mint: ctx.accounts.pass_mint.to_account_info(),
+            to: ctx.accounts.listing_pass_vault.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.pass_token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, 1, 0)?;
+        reservation.listed = true;
+
+        let listing = &mut ctx.accounts.listing;
+        listing.reservation = reservation.key();
+        listing.seller = ctx.accounts.seller.key();
+        listing.price = price;
+        listing.bump = ctx.bumps.listing;
+        listing.vault_bump = ctx.bumps.listing_pass_vault;
+
+        emit!(ReservationListedEvent {
+            reservation: reservation.key(),
+            seller: listing.seller,
+            price,
+            label: "reservationlisted".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_listing(ctx: Context<CancelListing>) -> Result<()> {
+        admin::release_listed_pass(
+            ctx.accounts.listing_pass_vault.to_account_info(),
+            ctx.accounts.seller_pass_account.to_account_info(),
+            ctx.accounts.pass_mint.to_account_info(),
+            ctx.accounts.listing.to_account_info(),
+            ctx.accounts.seller.to_account_info(),
+            &ctx.accounts.reservation.key(),
+            ctx.accounts.listing.bump,
+            ctx.accounts.pass_token_program.to_account_info(),
+        )?;
+        ctx.accounts.reservation.listed = false;
+
+        emit!(ListingCancelledEvent {
+            reservation: ctx.accounts.reservation.key(),
+            seller: ctx.accounts.seller.key(),
+            label: "listingcancelled".to_string()
+        });
+
+        Ok(())
+    }
+
+    // Compra atómica: el vendedor cobra, el propietario su comisión y el
+    // comprador recibe el pase y pasa a ser el huésped
+    pub fn buy_reservation(ctx: Context<BuyReservation>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let reservation = &mut ctx.accounts.reservation;
+        require!(
+            reservation.status == ReservationStatus::Booked,
+            ErrorCode::InvalidReservationStatus
+        );
+        require!(now < reservation.check_in_closes_at, ErrorCode::ResaleWindowClosed);
+
+        let price = ctx.accounts.listing.price;
+        let royalty = apply_bps(price, ctx.accounts.property.resale_royalty_bps);
+        let seller_amount = price - royalty;
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.buyer_token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.seller_token_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, seller_amount, ctx.accounts.mint.decimals)?;
+
+        if royalty > 0 {
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.owner_account.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+            token_interface::transfer_checked(cpi_ctx, royalty, ctx.accounts.mint.decimals)?;
+        }
+
+        admin::release_listed_pass(
+            ctx.accounts.listing_pass_vault.to_account_info(),
+            ctx.accounts.buyer_pass_account.to_account_info(),
+            ctx.accounts.pass_mint.to_account_info(),
+            ctx.accounts.listing.to_account_info(),
+            ctx.accounts.seller.to_account_info(),
+            &reservation.key(),
+            ctx.accounts.listing.bump,
+            ctx.accounts.pass_token_program.to_account_info(),
+        )?;
+
+        reservation.user = ctx.accounts.buyer.key();
+        reservation.listed = false;
+
+        emit!(ReservationResoldEvent {
+            reservation: reservation.key(),
+            seller: ctx.accounts.listing.seller,
+            buyer: reservation.user,
+            price,
+            royalty,
+            label: "reservationresold".to_string()
+        });
+
+        Ok(())
+    }
+
+    // La renta de la reserva y del escrow vuelve al huésped, así que no requiere firma
+    pub fn close_reservation(ctx: Context<CloseReservation>) -> Result<()> {
+        let reservation = &ctx.accounts.reservation;
+        require!(reservation.is_settled(), ErrorCode::ReservationNotSettled);
+        require!(
+            !reservation.is_group || ctx.accounts.group_booking.is_some(),
+            ErrorCode::GroupBookingRequired
+        );
+        require!(ctx.accounts.escrow_vault.amount == 0, ErrorCode::EscrowNotEmpty);
+
+        // Si el pase no se consumió en el check-in o al cancelar, se quema aquí
+        admin::retire_reservation_pass(
+            ctx.accounts.pass_mint.to_account_info(),
+            ctx.accounts.pass_account.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.user.to_account_info(),
+            &reservation.key(),
+            reservation.pass_bump,
+            ctx.accounts.pass_token_program.to_account_info(),
+        )?;
+
+        admin::close_escrow(
+            ctx.accounts.escrow_vault.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            &reservation.key(),
+            reservation.vault_bump,
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        Ok(())
+    }
+
+    // Cierra varias reservas; `remaining_accounts` va en grupos de (reserva, escrow, token,
+    // huésped, mint del pase, cuenta con el pase); si el pase ya se quemó, la última puede
+    // ser cualquier cuenta
+    pub fn close_reservations<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseReservations<'info>>,
+    ) -> Result<()> {
+        let groups = ctx.remaining_accounts.chunks_exact(6);
+        require!(
+            !ctx.remaining_accounts.is_empty() && groups.remainder().is_empty(),
+            ErrorCode::InvalidRemainingAccounts
+        );
+
+        for accounts in groups {
+            let (reservation_info, escrow_info, mint_info, user_info, pass_mint_info, pass_info) = (
+                &accounts[0],
+                &accounts[1],
+                &accounts[2],
+                &accounts[3],
+                &accounts[4],
+                &accounts[5],
+            );
+
+            let reservation = Account::<Reservation>::try_from(reservation_info)?;
+            require!(reservation.is_settled(), ErrorCode::ReservationNotSettled);
+            // Las de grupo se cierran una a una junto con su cuenta de grupo
+            require!(!reservation.is_group, ErrorCode::GroupBookingRequired);
+            require_keys_eq!(user_info.key(), reservation.user, ErrorCode::InvalidRemainingAccounts);
+
+            let escrow_address = Pubkey::create_program_address(
+                &[
+                    b"reservation-vault".as_ref(),
+                    reservation_info.key.as_ref(),
+                    &[reservation.vault_bump],
+                ],
+                ctx.program_id,
+            )
+            .map_err(|_| ErrorCode::InvalidRemainingAccounts)?;
+            require_keys_eq!(escrow_info.key(), escrow_address, ErrorCode::InvalidRemainingAccounts);
+            let escrow_vault = InterfaceAccount::<TokenAccount>::try_from(escrow_info)?;
+            require!(escrow_vault.amount == 0, ErrorCode::EscrowNotEmpty);
+            require_keys_eq!(mint_info.key(), escrow_vault.mint, ErrorCode::InvalidRemainingAccounts);
+            require_keys_eq!(pass_mint_info.key(), reservation.pass_mint, ErrorCode::InvalidRemainingAccounts);
+
+            admin::retire_reservation_pass(
+                pass_mint_info.clone(),
+                Some(pass_info.clone()),
+                user_info.clone(),
+                reservation_info.key,
+                reservation.pass_bump,
+                ctx.accounts.pass_token_program.to_account_info(),
+            )?;
+
+            admin::close_escrow(
+                escrow_info.clone(),
+                mint_info.clone(),
+                user_info.clone(),
+                reservation_info.key,
+                reservation.vault_bump,
+                ctx.accounts.token_program.to_account_info(),
+            )?;
+            reservation.close(user_info.clone())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        arbiter: Pubkey,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.arbiter = arbiter;
+        config.treasury = treasury;
+        config.yield_guest_bps = DEFAULT_YIELD_GUEST_BPS;
+        config.yield_owner_bps = DEFAULT_YIELD_OWNER_BPS;
+        config.platform_fee_bps = 0;
+        config.referral_fee_bps = 0;
+        config.bump = ctx.bumps.config;
+        Ok(())
+    }
+
+    pub fn set_arbiter(ctx: Context<UpdateConfig>, arbiter: Pubkey) -> Result<()> {
+        ctx.accounts.config.arbiter = arbiter;
+        Ok(())
+    }
+
+    pub fn set_treasury(ctx: Context<UpdateConfig>, treasury: Pubkey) -> Result<()> {
+        ctx.accounts.config.treasury = treasury;
+        Ok(())
+    }
+
+    pub fn set_yield_split(
+        ctx: Context<UpdateConfig>,
+        yield_guest_bps: u16,
+        yield_owner_bps: u16,
+    ) -> Result<()> {
+        require!(
+            yield_guest_bps as u64 + yield_owner_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidYieldSplit
+        );
+
+        let config = &mut ctx.accounts.config;
+        config.yield_guest_bps = yield_guest_bps;
+        config.yield_owner_bps = yield_owner_bps;
+        Ok(())
+    }
+
+    pub fn set_fee_config(
+        ctx: Context<UpdateConfig>,
+        platform_fee_bps: u16,
+        referral_fee_bps: u16,
+    ) -> Result<()> {
+        require!(
+            platform_fee_bps as u64 + referral_fee_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidFeeConfig
+        );
+
+        let config = &mut ctx.accounts.config;
+        config.platform_fee_bps = platform_fee_bps;
+        config.referral_fee_bps = referral_fee_bps;
+        Ok(())
+    }
+
+    // Solo los referidos aprobados por el administrador cobran comisión
+    pub fn approve_referrer(ctx: Context<ApproveReferrer>, referrer: Pubkey) -> Result<()> {
+        let approved_referrer = &mut ctx.accounts.approved_referrer;
+        approved_referrer.referrer = referrer;
+        approved_referrer.bump = ctx.bumps.approved_referrer;
+        Ok(())
+    }
+
+    pub fn revoke_referrer(_ctx: Context<RevokeReferrer>) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn init_staking_pool(ctx: Context<InitStakingPool>) -> Result<()> {
+        let pool = &mut ctx.accounts.staking_pool;
+        pool.mint = ctx.accounts.mint.key();
+        pool.reward_vault = ctx.accounts.reward_vault.key();
+        pool.total_staked = 0;
+        pool.reward_index = 0;
+        pool.bump = ctx.bumps.staking_pool;
+        Ok(())
+    }
+
+    // El administrador reparte recompensas entre todo lo que está en staking
+    pub fn deposit_rewards(ctx: Context<DepositRewards>, amount: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.staking_pool;
+        require!(pool.total_staked > 0, ErrorCode::NothingStaked);
+
+        let received = admin::transfer_in(
+            ctx.accounts.admin_token_account.to_account_info(),
+            ctx.accounts.reward_vault.to_account_info(),
+            ctx.accounts.admin.to_account_info(),
+            &ctx.accounts.mint,
+            amount,
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        staking::accrue_rewards(pool, received)
+    }
+
+    pub fn open_dispute(ctx: Context<OpenDispute>, reason_hash: [u8; 32]) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let reservation = &mut ctx.accounts.reservation;
+        let opened_by = ctx.accounts.authority.key();
+        require!(
+            opened_by == reservation.user || opened_by == ctx.accounts.property.owner,
+            ErrorCode::NotReservationParty
+        );
+
+        // Solo se puede disputar mientras queden fondos en el escrow
+        let disputable = match reservation.status {
+            ReservationStatus::Booked | ReservationStatus::CheckedIn => true,
+            ReservationStatus::CheckedOut => !reservation.deposit_released,
+            _ => false,
+        };
+        require!(disputable, ErrorCode::InvalidReservationStatus);
+
+        let dispute = &mut ctx.accounts.dispute;
+        dispute.reservation = reservation.key();
+        dispute.opened_by = opened_by;
+        dispute.previous_status = reservation.status;
+        dispute.reason_hash = reason_hash;
+        dispute.opened_at = now;
+        dispute.resolved_at = 0;
+        dispute.guest_amount = 0;
+        dispute.owner_amount = 0;
+        dispute.bump = ctx.bumps.dispute;
+
+        // Congelar el escrow hasta que resuelva el árbitro
+        reservation.status = ReservationStatus::Disputed;
+
+        emit!(DisputeOpenedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            opened_by,
+            reason_hash,
+            label: "disputeopened".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn resolve_dispute<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ResolveDispute<'info>>,
+        guest_amount: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let reservation = &mut ctx.accounts.reservation;
+        require!(
+            reservation.status == ReservationStatus::Disputed,
+            ErrorCode::InvalidReservationStatus
+        );
+
+        // El árbitro reparte todo lo que queda en el escrow
+        let escrowed = ctx.accounts.escrow_vault.amount;
+        require!(guest_amount <= escrowed, ErrorCode::InvalidDisputeSplit);
+        let owner_amount = escrowed - guest_amount;
+
+        admin::refund_guests(
+            ctx.accounts.escrow_vault.to_account_info(),
+            ctx.accounts.user_staking_account.to_account_info(),
+            ctx.accounts.group_booking.as_deref().map(|group| &**group),
+            ctx.remaining_accounts,
+            &ctx.accounts.settlement.mint,
+            reservation,
+            guest_amount,
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+        if owner_amount > 0 {
+            admin::transfer_from_escrow(
+                ctx.accounts.escrow_vault.to_account_info(),
+                ctx.accounts.owner_account.to_account_info(),
+                &ctx.accounts.settlement.mint,
+                &reservation.key(),
+                reservation.vault_bump,
+                owner_amount,
+                ctx.accounts.token_program.to_account_info(),
+            )?;
+        }
+
+        // Si la disputa se abrió antes del check-in los fondos siguen en staking
+        ctx.accounts.settlement.settle_yield(
+            reservation,
+            &ctx.accounts.escrow_vault.mint,
+            ctx.accounts.user_staking_account.to_account_info(),
+            ctx.accounts.owner_account.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        // Si no llegó a hacerse el check-in las noches vuelven a estar libres
+        if ctx.accounts.dispute.previous_status == ReservationStatus::Booked {
+            let calendars = calendar::stay_calendars(&ctx.accounts.calendar, &ctx.accounts.next_calendar);
+            calendar::release_nights(
+                &calendars,
+                &reservation.property,
+                reservation.check_in_date,
+                reservation.check_out_date,
+            )?;
+        }
+
+        let dispute = &mut ctx.accounts.dispute;
+        dispute.resolved_at = now;
+        dispute.guest_amount = guest_amount;
+        dispute.owner_amount = owner_amount;
+
+        reservation.deposit_released = true;
+        reservation.status = ReservationStatus::Resolved;
+
+        emit!(DisputeResolvedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            arbiter: ctx.accounts.arbiter.key(),
+            guest_amount,
+            owner_amount,
+            label: "disputeresolved".to_string()
+        });
+
+        Ok(())
+    }
+
+    // Oráculo de precios gestionado por su autoridad, solo para pruebas locales y devnet
+    pub fn init_price_feed(
+        ctx: Context<InitPriceFeed>,
+        price: i64,
+        expo: i32,
+        conf: u64,
+    ) -> Result<()> {
+        require!(cfg!(feature = "mock-oracle"), ErrorCode::MockOracleDisabled);
+
+        let price_feed = &mut ctx.accounts.price_feed;
+        price_feed.authority = ctx.accounts.authority.key();
+        price_feed.price = price;
+        price_feed.expo = expo;
+        price_feed.conf = conf;
+        price_feed.publish_time = Clock::get()?.unix_timestamp;
+        Ok(())
+    }
+
+    pub fn set_price(
+        ctx: Context<SetPrice>,
+        price: i64,
+        expo: i32,
+        conf: u64,
+        publish_time: i64,
+    ) -> Result<()> {
+        require!(cfg!(feature = "mock-oracle"), ErrorCode::MockOracleDisabled);
+
+        let price_feed = &mut ctx.accounts.price_feed;
+        price_feed.price = price;
+        price_feed.expo = expo;
+        price_feed.conf = conf;
+        price_feed.publish_time = publish_time;
+        Ok(())
+    }
+
+    pub fn init_calendar(ctx: Context<InitCalendar>, year: u16) -> Result<()> {
+        require!(year >= 1970, ErrorCode::CalendarMismatch);
+
+        let mut calendar = ctx.accounts.calendar.load_init()?;
+        calendar.property = ctx.accounts.property.key();
+        calendar.year = year;
+        calendar.bump = ctx.bumps.calendar;
+        Ok(())
+    }
+
+    // Retira noches de la venta sin crear una reserva; falla si alguna ya está reservada
+    pub fn block_dates(
+        ctx: Context<BlockDates>,
+        start_date: i64,
+        end_date: i64,
+        reason: BlockReason,
+    ) -> Result<()> {
+        ctx.accounts.property.authorize(
+            &ctx.accounts.authority.key(),
+            ctx.accounts.role.as_deref(),
+            Permission::BlockDates,
+        )?;
+        require!(
+            calendar::nights(start_date, end_date) > 0,
+            ErrorCode::InvalidBlockDates
+        );
+
+        let calendars = calendar::stay_calendars(&ctx.accounts.calendar, &ctx.accounts.next_calendar);
+        calendar::reserve_nights(
+            &calendars,
+            &ctx.accounts.property.key(),
+            start_date,
+            end_date,
+        )?;
+
+        let date_block = &mut ctx.accounts.date_block;
+        date_block.property = ctx.accounts.property.key();
+        date_block.start_date = start_date;
+        date_block.end_date = end_date;
+        date_block.reason = reason;
+        date_block.payer = ctx.accounts.authority.key();
+        date_block.bump = ctx.bumps.date_block;
+
+        emit!(DatesBlockedEvent {
+            property: date_block.property,
+            start_date,
+            end_date,
+            reason,
+            blocked_by: date_block.payer,
+            label: "datesblocked".to_string()
+        });
+
+        Ok(())
+    }
+
+    pub fn unblock_dates(ctx: Context<UnblockDates>) -> Result<()> {
+        ctx.accounts.property.authorize(
+            &ctx.accounts.authority.key(),
+            ctx.accounts.role.as_deref(),
+            Permission::BlockDates,
+        )?;
+
+        // Las noches del bloqueo no pueden estar en ninguna reserva, así que se liberan todas
+        let date_block = &ctx.accounts.date_block;
+        let calendars = calendar::stay_calendars(&ctx.accounts.calendar, &ctx.accounts.next_calendar);
+        calendar::release_nights(
+            &calendars,
+            &date_block.property,
+            date_block.start_date,
+            date_block.end_date,
+        )?;
+
+        emit!(DatesUnblockedEvent {
+            property: date_block.property,
+            start_date: date_block.start_date,
+            end_date: date_block.end_date,
+            unblocked_by: ctx.accounts.authority.key(),
+            label: "datesunblocked".to_string()
+        });
+
+        Ok(())
+    }
+}
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const MAX_REFUND_TIERS: usize = 4;
+pub const MAX_RATE_OVERRIDES: usize = 16;
+pub const MAX_STAY_DISCOUNTS: usize = 4;
+// 30% al hacer check-in y el resto 48 horas después del check-out
+pub const DEFAULT_CHECK_IN_PAYOUT_BPS: u16 = 3_000;
+pub const DEFAULT_PAYOUT_DELAY: i64 = 172800;
+pub const DEFAULT_DAMAGE_CLAIM_WINDOW: i64 = 259200;
+pub const MAX_DAMAGE_CLAIM_WINDOW: i64 = 2592000;
+pub const DEFAULT_MODIFICATION_CUTOFF: i64 = 172800;
+pub const DEFAULT_APPROVAL_TIMEOUT: i64 = 86400;
+pub const MAX_GROUP_GUESTS: usize = 8;
+// Reglas de reserva por defecto: de 1 a 365 noches, hasta 16 huéspedes y un año de antelación
+pub const DEFAULT_MIN_NIGHTS: u16 = 1;
+pub const DEFAULT_MAX_NIGHTS: u16 = 365;
+pub const DEFAULT_MAX_GUESTS: u8 = 16;
+pub const DEFAULT_MAX_BOOKING_HORIZON: i64 = 31536000;
+// Por defecto el check-in se admite durante las 48 horas siguientes a la fecha de entrada
+pub const DEFAULT_CHECK_IN_LATEST_OFFSET: i64 = 172800;
+// La ventana de check-in no puede alejarse más de una semana de la fecha de entrada
+pub const MAX_CHECK_IN_OFFSET: i64 = 604800;
+// Husos horarios válidos, de UTC-12 a UTC+14, en segundos
+pub const MIN_UTC_OFFSET: i32 = -43200;
+pub const MAX_UTC_OFFSET: i32 = 50400;
+
+// Reparto por defecto del rendimiento: 50% huésped, 30% propietario y el resto plataforma
+pub const DEFAULT_YIELD_GUEST_BPS: u16 = 5_000;
+pub const DEFAULT_YIELD_OWNER_BPS: u16 = 3_000;
+
+pub fn apply_bps(amount: u64, bps: u16) -> u64 {
+    (amount as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64
+}
+
+// Periodos ordenados y sin solapes; descuentos de menor a mayor duración
+fn validate_rate_schedule(
+    weekday_multipliers_bps: &[u16; 7],
+    overrides: &[RateOverride],
+    stay_discounts: &[StayDiscount],
+) -> Result<()> {
+    require!(
+        weekday_multipliers_bps.iter().all(|bps| *bps > 0)
+            && overrides.len() <= MAX_RATE_OVERRIDES
+            && stay_discounts.len() <= MAX_STAY_DISCOUNTS,
+        ErrorCode::InvalidRateSchedule
+    );
+    for (i, rate_override) in overrides.iter().enumerate() {
+        require!(
+            rate_override.start_date < rate_override.end_date && rate_override.nightly_rate > 0,
+            ErrorCode::InvalidRateSchedule
+        );
+        if i > 0 {
+            require!(
+                rate_override.start_date >= overrides[i - 1].end_date,
+                ErrorCode::InvalidRateSchedule
+            );
+        }
+    }
+    for (i, discount) in stay_discounts.iter().enumerate() {
+        require!(
+            discount.min_nights > 0 && discount.discount_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidRateSchedule
+        );
+        if i > 0 {
+            require!(
+                discount.min_nights > stay_discounts[i - 1].min_nights,
+                ErrorCode::InvalidRateSchedule
+            );
+        }
+    }
+    Ok(())
+}
+
+// Los tramos deben ir ordenados de mayor a menor antelación
+fn validate_refund_tiers(tiers: &[RefundTier]) -> Result<()> {
+    require!(
+        !tiers.is_empty() && tiers.len() <= MAX_REFUND_TIERS,
+        ErrorCode::InvalidRefundPolicy
+    );
+    for (i, tier) in tiers.iter().enumerate() {
+        require!(
+            tier.refund_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidRefundPolicy
+        );
+        if i > 0 {
+            require!(
+                tier.min_notice < tiers[i - 1].min_notice,
+                ErrorCode::InvalidRefundPolicy
+            );
+        }
+    }
+    Ok(())
+}
+
+
+// El referido tiene que estar aprobado y no puede ser quien reserva
+fn validate_referrer(
+    referrer: Option<Pubkey>,
+    approved_referrer: Option<&ApprovedReferrer>,
+    guest: &Pubkey,
+) -> Result<Pubkey> {
+    let Some(referrer) = referrer else {
+        return Ok(Pubkey::default());
+    };
+    let approved_referrer = approved_referrer.ok_or(ErrorCode::InvalidReferrer)?;
+    require_keys_eq!(approved_referrer.referrer, referrer, ErrorCode::InvalidReferrer);
+    require_keys_neq!(referrer, *guest, ErrorCode::InvalidReferrer);
+    Ok(referrer)
+}
+
+fn validate_group_shares(shares: &[GuestShareInput], total: u64) -> Result<()> {
+    require!(
+        !shares.is_empty() && shares.len() <= MAX_GROUP_GUESTS,
+        ErrorCode::InvalidGroupShares
+    );
+    let mut sum: u64 = 0;
+    for (i, share) in shares.iter().enumerate() {
+        require!(
+            share.amount > 0
+                && shares[..i].iter().all(|other| other.guest != share.guest),
+            ErrorCode::InvalidGroupShares
+        );
+        sum = sum.checked_add(share.amount).ok_or(ErrorCode::MathOverflow)?;
+    }
+    require!(sum == total, ErrorCode::InvalidGroupShares);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateReservation<'info> {
+    #[account(init, payer = user, space = 8 + 32 + 32 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 32 + 8 + 1 + 8 + 16 + 8 + 32 + 8 + 32 + 32 + 1 + 1 + (4 + MAX_REFUND_TIERS * (8 + 2)) + 2 + 8 + 2 + 8 + 8 + 1 + 1 + 1)]
+    pub reservation: Account<'info, Reservation>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"property".as_ref(), property.owner.as_ref(), property.property_id.to_le_bytes().as_ref()],
+        bump = property.bump
+    )]
+    pub property: Account<'info, Property>,
+    #[account(
+        seeds = [b"rate-schedule".as_ref(), property.key().as_ref()],
+        bump = rate_schedule.bump
+    )]
+    pub rate_schedule: Option<Box<Account<'info, RateSchedule>>>,
+    // Su política de cancelación queda fijada en la reserva
+    #[account(
+        seeds = [b"cancellation-policy".as_ref(), property.key().as_ref()],
+        bump = cancellation_policy.bump
+    )]
+    pub cancellation_policy: Option<Box<Account<'info, CancellationPolicy>>>,
+    #[account(mut, constraint = user_staking_account.mint == mint.key() @ ErrorCode::InvalidMint)]
+    pub user_staking_account: InterfaceAccount<'info, TokenAccount>,
+    // El token de la propiedad o uno de los aceptados por el propietario
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"accepted-mint".as_ref(), property.key().as_ref(), mint.key().as_ref()],
+        bump = accepted_mint.bump
+    )]
+    pub accepted_mint: Option<Box<Account<'info, AcceptedMint>>>,
+    /// CHECK: validado en oracle::load_price
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    // Solo si la reserva lleva referido
+    #[account(
+        seeds = [b"referrer".as_ref(), approved_referrer.referrer.as_ref()],
+        bump = approved_referrer.bump
+    )]
+    pub approved_referrer: Option<Account<'info, ApprovedReferrer>>,
+    // Escrow propiedad del programa que custodia los fondos de la reserva
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow_vault,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    // Pase de la reserva: token único cuyo titular puede hacer el check-in
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"reservation-pass".as_ref(), reservation.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = pass_mint,
+        mint::freeze_authority = pass_mint,
+        mint::token_program = pass_token_program,
+        extensions::close_authority::authority = pass_mint,
+        extensions::permanent_delegate::delegate = pass_mint,
+    )]
+    pub pass_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = pass_mint,
+        associated_token::authority = user,
+        associated_token::token_program = pass_token_program,
+    )]
+    pub user_pass_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    // Solo necesario si la estancia cruza el fin de año
+    #[account(mut)]
+    pub next_calendar: Option<AccountLoader<'info, AvailabilityCalendar>>,
+    #[account(mut, seeds = [b"staking-pool".as_ref(), mint.key().as_ref()], bump = staking_pool.bump)]
+    pub staking_pool: Box<Account<'info, StakingPool>>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    // El pase va siempre en Token-2022 para poder quemarlo y cerrar su mint
+    pub pass_token_program: Program<'info, Token2022>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+pub struct CreateGroupReservation<'info> {
+    #[account(init, payer = organizer, space = 8 + 32 + 32 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 32 + 8 + 1 + 8 + 16 + 8 + 32 + 8 + 32 + 32 + 1 + 1 + (4 + MAX_REFUND_TIERS * (8 + 2)) + 2 + 8 + 2 + 8 + 8 + 1 + 1 + 1)]
+    pub reservation: Account<'info, Reservation>,
+    #[account(
+        init,
+        payer = organizer,
+        space = 8 + 32 + 32 + 8 + 8 + (4 + MAX_GROUP_GUESTS * (32 + 8 + 1 + 8)) + 1,
+        seeds = [b"group-booking".as_ref(), reservation.key().as_ref()],
+        bump
+    )]
+    pub group_booking: Account<'info, GroupBooking>,
+    #[account(mut)]
+    pub organizer: Signer<'info>,
+    #[account(
+        seeds = [b"property".as_ref(), property.owner.as_ref(), property.property_id.to_le_bytes().as_ref()],
+        bump = property.bump
+    )]
+    pub property: Account<'info, Property>,
+    #[account(
+        seeds = [b"rate-schedule".as_ref(), property.key().as_ref()],
+        bump = rate_schedule.bump
+    )]
+    pub rate_schedule: Option<Box<Account<'info, RateSchedule>>>,
+    // Su política de cancelación queda fijada en la reserva
+    #[account(
+        seeds = [b"cancellation-policy".as_ref(), property.key().as_ref()],
+        bump = cancellation_policy.bump
+    )]
+    pub cancellation_policy: Option<Box<Account<'info, CancellationPolicy>>>,
+    // Solo si la reserva lleva referido
+    #[account(
+        seeds = [b"referrer".as_ref(), approved_referrer.referrer.as_ref()],
+        bump = approved_referrer.bump
+    )]
+    pub approved_referrer: Option<Account<'info, ApprovedReferrer>>,
+    #[account(constraint = mint.key() == property.mint @ ErrorCode::InvalidMint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = organizer,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow_vault,
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    // Pase de la reserva: token único cuyo titular puede hacer el check-in
+    #[account(
+        init,
+        payer = organizer,
+        seeds = [b"reservation-pass".as_ref(), reservation.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = pass_mint,
+        mint::freeze_authority = pass_mint,
+        mint::token_program = pass_token_program,
+        extensions::close_authority::authority = pass_mint,
+        extensions::permanent_delegate::delegate = pass_mint,
+    )]
+    pub pass_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = organizer,
+        associated_token::mint = pass_mint,
+        associated_token::authority = organizer,
+        associated_token::token_program = pass_token_program,
+    )]
+    pub organizer_pass_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    // Solo necesario si la estancia cruza el fin de año
+    #[account(mut)]
+    pub next_calendar: Option<AccountLoader<'info, AvailabilityCalendar>>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    // El pase va siempre en Token-2022 para poder quemarlo y cerrar su mint
+    pub pass_token_program: Program<'info, Token2022>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ContributeToReservation<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    #[account(
+        mut,
+        has_one = reservation,
+        seeds = [b"group-booking".as_ref(), reservation.key().as_ref()],
+        bump = group_booking.bump
+    )]
+    pub group_booking: Account<'info, GroupBooking>,
+    pub guest: Signer<'info>,
+    pub property: Account<'info, Property>,
+    #[account(
+        mut,
+        constraint = guest_token_account.owner == guest.key(),
+        constraint = guest_token_account.mint == property.mint @ ErrorCode::InvalidMint
+    )]
+    pub guest_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = property.mint @ ErrorCode::InvalidMint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, seeds = [b"staking-pool".as_ref(), property.mint.as_ref()], bump = staking_pool.bump)]
+    pub staking_pool: Box<Account<'info, StakingPool>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireGroupReservation<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    // Se cierra junto con la reserva en close_reservation
+    #[account(
+        has_one = reservation,
+        seeds = [b"group-booking".as_ref(), reservation.key().as_ref()],
+        bump = group_booking.bump
+    )]
+    pub group_booking: Account<'info, GroupBooking>,
+    pub property: Account<'info, Property>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = escrow_vault.mint @ ErrorCode::InvalidMint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    #[account(mut)]
+    pub next_calendar: Option<AccountLoader<'info, AvailabilityCalendar>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CheckIn<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    // El titular del pase, que no tiene por qué ser quien reservó
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        constraint = user_pass_account.mint == reservation.pass_mint
+            && user_pass_account.owner == user.key()
+            && user_pass_account.amount == 1 @ ErrorCode::NotPassHolder
+    )]
+    pub user_pass_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: mint del pase, que se cierra al quemarlo
+    #[account(mut, address = reservation.pass_mint)]
+    pub pass_mint: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"property".as_ref(), property.owner.as_ref(), property.property_id.to_le_bytes().as_ref()],
+        bump = property.bump
+    )]
+    pub property: Account<'info, Property>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = owner_account.key() == reservation.payout_account @ ErrorCode::InvalidPayoutAccount)]
+    pub owner_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = user_staking_account.owner == user.key())]
+    pub user_staking_account: InterfaceAccount<'info, TokenAccount>,
+    pub settlement: Settlement<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub pass_token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct CheckOut<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    // El huésped, o cualquiera una vez cerrado el plazo de disputa
+    pub authority: Signer<'info>,
+    pub property: Account<'info, Property>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = owner_account.key() == reservation.payout_account @ ErrorCode::InvalidPayoutAccount)]
+    pub owner_account: InterfaceAccount<'info, TokenAccount>,
+    pub settlement: Settlement<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimDamages<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    #[account(has_one = owner)]
+    pub property: Account<'info, Property>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseDeposit<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    pub property: Account<'info, Property>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = escrow_vault.mint @ ErrorCode::InvalidMint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, constraint = user_staking_account.owner == reservation.user)]
+    pub user_staking_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = owner_account.key() == reservation.payout_account @ ErrorCode::InvalidPayoutAccount)]
+    pub owner_account: InterfaceAccount<'info, TokenAccount>,
+    // Solo en reservas de grupo: el reembolso se reparte entre los huéspedes, cuyas
+    // cuentas de token van en `remaining_accounts` en el orden de las partes
+    #[account(
+        seeds = [b"group-booking".as_ref(), reservation.key().as_ref()],
+        bump = group_booking.bump
+    )]
+    pub group_booking: Option<Box<Account<'info, GroupBooking>>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(tiers: Vec<RefundTier>)]
+pub struct InitCancellationPolicy<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + 32 + 32 + (4 + MAX_REFUND_TIERS * (8 + 2)) + 1,
+        seeds = [b"cancellation-policy".as_ref(), property.key().as_ref()],
+        bump
+    )]
+    pub cancellation_policy: Account<'info, CancellationPolicy>,
+    #[account(has_one = owner)]
+    pub property: Account<'info, Property>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitRateSchedule<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + 32 + 32 + 2 * 7
+            + (4 + MAX_RATE_OVERRIDES * (8 + 8 + 8))
+            + (4 + MAX_STAY_DISCOUNTS * (2 + 2))
+            + 1,
+        seeds = [b"rate-schedule".as_ref(), property.key().as_ref()],
+        bump
+    )]
+    pub rate_schedule: Account<'info, RateSchedule>,
+    #[account(mut, has_one = owner)]
+    pub property: Account<'info, Property>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRateSchedule<'info> {
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"rate-schedule".as_ref(), rate_schedule.property.as_ref()],
+        bump = rate_schedule.bump
+    )]
+    pub rate_schedule: Account<'info, RateSchedule>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCancellationPolicy<'info> {
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"cancellation-policy".as_ref(), cancellation_policy.property.as_ref()],
+        bump = cancellation_policy.bump
+    )]
+    pub cancellation_policy: Account<'info, CancellationPolicy>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelReservation<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        constraint = user_pass_account.mint == reservation.pass_mint
+            && user_pass_account.owner == user.key()
+            && user_pass_account.amount == 1 @ ErrorCode::NotPassHolder
+    )]
+    pub user_pass_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: mint del pase, que se cierra al quemarlo
+    #[account(mut, address = reservation.pass_mint)]
+    pub pass_mint: UncheckedAccount<'info>,
+    pub property: Account<'info, Property>,
+    #[account(mut, constraint = user_staking_account.owner == user.key())]
+    pub user_staking_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = owner_account.key() == reservation.payout_account @ ErrorCode::InvalidPayoutAccount)]
+    pub owner_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    // Solo necesario si la estancia cruza el fin de año
+    #[account(mut)]
+    pub next_calendar: Option<AccountLoader<'info, AvailabilityCalendar>>,
+    // Solo en reservas de grupo: el reembolso se reparte entre los huéspedes, cuyas
+    // cuentas de token van en `remaining_accounts` en el orden de las partes
+    #[account(
+        seeds = [b"group-booking".as_ref(), reservation.key().as_ref()],
+        bump = group_booking.bump
+    )]
+    pub group_booking: Option<Box<Account<'info, GroupBooking>>>,
+    pub settlement: Settlement<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub pass_token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveReservation<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    pub property: Account<'info, Property>,
+    // El propietario o un miembro con permiso para aprobar
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"property-role".as_ref(), property.key().as_ref(), authority.key().as_ref()],
+        bump = role.bump
+    )]
+    pub role: Option<Account<'info, PropertyRole>>,
+}
+
+#[derive(Accounts)]
+pub struct RejectReservation<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    // El propietario o un miembro con permiso al rechazar; cualquiera al caducar
+    pub authority: Signer<'info>,
+    pub property: Account<'info, Property>,
+    #[account(
+        seeds = [b"property-role".as_ref(), property.key().as_ref(), authority.key().as_ref()],
+        bump = role.bump
+    )]
+    pub role: Option<Account<'info, PropertyRole>>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = user_staking_account.owner == reservation.user)]
+    pub user_staking_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = owner_account.key() == reservation.payout_account @ ErrorCode::InvalidPayoutAccount)]
+    pub owner_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    #[account(mut)]
+    pub next_calendar: Option<AccountLoader<'info, AvailabilityCalendar>>,
+    // Solo en reservas de grupo: el reembolso se reparte entre los huéspedes, cuyas
+    // cuentas de token van en `remaining_accounts` en el orden de las partes
+    #[account(
+        seeds = [b"group-booking".as_ref(), reservation.key().as_ref()],
+        bump = group_booking.bump
+    )]
+    pub group_booking: Option<Box<Account<'info, GroupBooking>>>,
+    pub settlement: Settlement<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> RejectReservation<'info> {
+    // Devuelve al huésped todo lo depositado y libera las noches retenidas
+    fn refund_request(
+        &mut self,
+        guest_accounts: &'info [AccountInfo<'info>],
+        status: ReservationStatus,
+    ) -> Result<()> {
+        let reservation = &mut self.reservation;
+        require!(
+            reservation.status == ReservationStatus::Pending,
+            ErrorCode::InvalidReservationStatus
+        );
+
+        let refund = reservation.amount + reservation.deposit;
+        admin::refund_guests(
+            self.escrow_vault.to_account_info(),
+            self.user_staking_account.to_account_info(),
+            self.group_booking.as_deref().map(|group| &**group),
+            guest_accounts,
+            &self.settlement.mint,
+            reservation,
+            refund,
+            self.token_program.to_account_info(),
+        )?;
+
+        self.settlement.settle_yield(
+            reservation,
+            &self.escrow_vault.mint,
+            self.user_staking_account.to_account_info(),
+            self.owner_account.to_account_info(),
+            self.token_program.to_account_info(),
+        )?;
+
+        let calendars = calendar::stay_calendars(&self.calendar, &self.next_calendar);
+        calendar::release_nights(
+            &calendars,
+            &reservation.property,
+            reservation.check_in_date,
+            reservation.check_out_date,
+        )?;
+
+        reservation.deposit_released = true;
+        reservation.status = status;
+
+        emit!(ReservationRejectedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest: reservation.user,
+            refund,
+            status,
+            label: "reservationrejected".to_string()
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct ModifyReservation<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    pub user: Signer<'info>,
+    #[account(
+        constraint = user_pass_account.mint == reservation.pass_mint
+            && user_pass_account.owner == user.key()
+            && user_pass_account.amount == 1 @ ErrorCode::NotPassHolder
+    )]
+    pub user_pass_account: InterfaceAccount<'info, TokenAccount>,
+    pub property: Account<'info, Property>,
+    #[account(
+        seeds = [b"rate-schedule".as_ref(), property.key().as_ref()],
+        bump = rate_schedule.bump
+    )]
+    pub rate_schedule: Option<Box<Account<'info, RateSchedule>>>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = escrow_vault.mint @ ErrorCode::InvalidMint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, constraint = user_staking_account.owner == user.key())]
+    pub user_staking_account: InterfaceAccount<'info, TokenAccount>,
+    // Deben cubrir tanto las fechas antiguas como las nuevas
+    #[account(mut)]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    #[account(mut)]
+    pub next_calendar: Option<AccountLoader<'info, AvailabilityCalendar>>,
+    #[account(
+        mut,
+        seeds = [b"staking-pool".as_ref(), escrow_vault.mint.as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Box<Account<'info, StakingPool>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(property_id: u64)]
+pub struct RegisterProperty<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + 32 + 8 + 32 + 32 + 1 + 8 + 8 + 1 + 8 + 8 + 4 + 2 + 8 + 8 + 8 + 2 + 1 + 8 + 1 + 8 + 1 + 2 + 2 + 32 + 32 + 2 + 2 + 2 + 1 + 8 + 8 + 1 + 1,
+        seeds = [b"property".as_ref(), owner.key().as_ref(), property_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub property: Account<'info, Property>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(constraint = payout_account.mint == mint.key() @ ErrorCode::InvalidMint)]
+    pub payout_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProperty<'info> {
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"property".as_ref(), owner.key().as_ref(), property.property_id.to_le_bytes().as_ref()],
+        bump = property.bump
+    )]
+    pub property: Account<'info, Property>,
+    #[account(constraint = payout_account.mint == property.mint @ ErrorCode::InvalidMint)]
+    pub payout_account: InterfaceAccount<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureProperty<'info> {
+    #[account(mut, has_one = owner)]
+    pub property: Account<'info, Property>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SettleNoShow<'info> {
+    #[account(mut, has_one = property, has_one = user, close = user)]
+    pub reservation: Account<'info, Reservation>,
+    // Recibe la renta de la reserva y del escrow
+    /// CHECK: validado con has_one en la reserva
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+    pub property: Account<'info, Property>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    // Recibe las comisiones de transferencia retenidas en el escrow antes de cerrarlo
+    #[account(mut, address = escrow_vault.mint @ ErrorCode::InvalidMint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, constraint = user_staking_account.owner == reservation.user)]
+    pub user_staking_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = owner_account.key() == reservation.payout_account @ ErrorCode::InvalidPayoutAccount)]
+    pub owner_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    // Solo necesario si la estancia cruza el fin de año
+    #[account(mut)]
+    pub next_calendar: Option<AccountLoader<'info, AvailabilityCalendar>>,
+    // Solo en reservas de grupo, que se cierran junto con la reserva
+    #[account(
+        mut,
+        has_one = organizer,
+        close = organizer,
+        seeds = [b"group-booking".as_ref(), reservation.key().as_ref()],
+        bump = group_booking.bump
+    )]
+    pub group_booking: Option<Box<Account<'info, GroupBooking>>>,
+    /// CHECK: validado con has_one en la reserva de grupo; recupera su renta
+    #[account(mut)]
+    pub organizer: Option<UncheckedAccount<'info>>,
+    /// CHECK: mint del pase; ya está cerrado si el pase se usó en el check-in o al cancelar
+    #[account(mut, address = reservation.pass_mint)]
+    pub pass_mint: UncheckedAccount<'info>,
+    // Cuenta que tiene el pase, solo si aún no se ha quemado
+    #[account(mut, constraint = pass_account.mint == reservation.pass_mint @ ErrorCode::InvalidMint)]
+    pub pass_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    // Solo si la reserva está a la venta: la oferta se cierra junto con la reserva
+    #[account(
+        mut,
+        has_one = reservation,
+        has_one = seller,
+        close = seller,
+        seeds = [b"listing".as_ref(), reservation.key().as_ref()],
+        bump = listing.bump
+    )]
+    pub listing: Option<Box<Account<'info, Listing>>>,
+    /// CHECK: validado con has_one en la oferta; recupera su renta
+    #[account(mut)]
+    pub seller: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        seeds = [b"listing-pass".as_ref(), reservation.key().as_ref()],
+        bump = listing.as_ref().map(|listing| listing.vault_bump).unwrap_or_default()
+    )]
+    pub listing_pass_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub settlement: Settlement<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub pass_token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct ListReservation<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    pub property: Account<'info, Property>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        constraint = seller_pass_account.mint == reservation.pass_mint
+            && seller_pass_account.owner == seller.key()
+            && seller_pass_account.amount == 1 @ ErrorCode::NotPassHolder
+    )]
+    pub seller_pass_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reservation.pass_mint)]
+    pub pass_mint: InterfaceAccount<'info, Mint>,
+    // El tope de reventa se calcula sobre el importe, que solo está en el token de la
+    // propiedad si se pagó con él; el comprador siempre paga en ese token
+    #[account(
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump,
+        constraint = escrow_vault.mint == property.mint @ ErrorCode::ResaleNotAllowed
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + 32 + 32 + 8 + 1 + 1,
+        seeds = [b"listing".as_ref(), reservation.key().as_ref()],
+        bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        init,
+        payer = seller,
+        seeds = [b"listing-pass".as_ref(), reservation.key().as_ref()],
+        bump,
+        token::mint = pass_mint,
+        token::authority = listing,
+        token::token_program = pass_token_program,
+    )]
+    pub listing_pass_vault: InterfaceAccount<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub pass_token_program: Program<'info, Token2022>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CancelListing<'info> {
+    #[account(mut)]
+    pub reservation: Account<'info, Reservation>,
+    #[account(
+        mut,
+        has_one = reservation,
+        has_one = seller,
+        close = seller,
+        seeds = [b"listing".as_ref(), reservation.key().as_ref()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        constraint = seller_pass_account.mint == reservation.pass_mint
+            && seller_pass_account.owner == seller.key()
+    )]
+    pub seller_pass_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reservation.pass_mint)]
+    pub pass_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"listing-pass".as_ref(), reservation.key().as_ref()],
+        bump = listing.vault_bump
+    )]
+    pub listing_pass_vault: InterfaceAccount<'info, TokenAccount>,
+    pub pass_token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct BuyReservation<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    pub property: Account<'info, Property>,
+    #[account(
+        mut,
+        has_one = reservation,
+        has_one = seller,
+        close = seller,
+        seeds = [b"listing".as_ref(), reservation.key().as_ref()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    /// CHECK: validado con has_one en la oferta; recibe la renta de la oferta
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = seller_token_account.owner == seller.key(),
+        constraint = seller_token_account.mint == property.mint @ ErrorCode::InvalidMint
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key(),
+        constraint = buyer_token_account.mint == property.mint @ ErrorCode::InvalidMint
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = buyer_pass_account.owner == buyer.key(),
+        constraint = buyer_pass_account.mint == reservation.pass_mint @ ErrorCode::InvalidMint
+    )]
+    pub buyer_pass_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = property.mint @ ErrorCode::InvalidMint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(address = reservation.pass_mint)]
+    pub pass_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"listing-pass".as_ref(), reservation.key().as_ref()],
+        bump = listing.vault_bump
+    )]
+    pub listing_pass_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = owner_account.key() == property.payout_account @ ErrorCode::InvalidPayoutAccount)]
+    pub owner_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub pass_token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct CloseReservation<'info> {
+    #[account(mut, has_one = user, close = user)]
+    pub reservation: Account<'info, Reservation>,
+    /// CHECK: validado con has_one en la reserva
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = escrow_vault.mint @ ErrorCode::InvalidMint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    // Solo en reservas de grupo, que se cierran junto con la reserva
+    #[account(
+        mut,
+        has_one = organizer,
+        close = organizer,
+        seeds = [b"group-booking".as_ref(), reservation.key().as_ref()],
+        bump = group_booking.bump
+    )]
+    pub group_booking: Option<Box<Account<'info, GroupBooking>>>,
+    /// CHECK: validado con has_one en la reserva de grupo; recupera su renta
+    #[account(mut)]
+    pub organizer: Option<UncheckedAccount<'info>>,
+    /// CHECK: mint del pase; ya está cerrado si el pase se usó en el check-in o al cancelar
+    #[account(mut, address = reservation.pass_mint)]
+    pub pass_mint: UncheckedAccount<'info>,
+    // Cuenta que tiene el pase, solo si aún no se ha quemado
+    #[account(mut, constraint = pass_account.mint == reservation.pass_mint @ ErrorCode::InvalidMint)]
+    pub pass_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub pass_token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct CloseReservations<'info> {
+    pub token_program: Interface<'info, TokenInterface>,
+    pub pass_token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(init, payer = admin, space = 8 + 32 + 32 + 32 + 2 + 2 + 2 + 2 + 1, seeds = [b"config".as_ref()], bump)]
+    pub config: Account<'info, Config>,
+    // Solo quien puede actualizar el programa lo configura por primera vez
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ ErrorCode::Unauthorized)]
+    pub program: Program<'info, crate::program::ReservaProperty>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ ErrorCode::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut, has_one = admin, seeds = [b"config".as_ref()], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(referrer: Pubkey)]
+pub struct ApproveReferrer<'info> {
+    #[account(seeds = [b"config".as_ref()], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 1,
+        seeds = [b"referrer".as_ref(), referrer.as_ref()],
+        bump
+    )]
+    pub approved_referrer: Account<'info, ApprovedReferrer>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeReferrer<'info> {
+    #[account(seeds = [b"config".as_ref()], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [b"referrer".as_ref(), approved_referrer.referrer.as_ref()],
+        bump = approved_referrer.bump
+    )]
+    pub approved_referrer: Account<'info, ApprovedReferrer>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenDispute<'info> {
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    pub property: Account<'info, Property>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 32 + 1 + 32 + 8 + 8 + 8 + 8 + 1,
+        seeds = [b"dispute".as_ref(), reservation.key().as_ref()],
+        bump
+    )]
+    pub dispute: Account<'info, Dispute>,
+    // El huésped o el propietario
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(seeds = [b"config".as_ref()], bump = config.bump, has_one = arbiter)]
+    pub config: Account<'info, Config>,
+    pub arbiter: Signer<'info>,
+    #[account(mut, has_one = property)]
+    pub reservation: Account<'info, Reservation>,
+    pub property: Account<'info, Property>,
+    #[account(
+        mut,
+        has_one = reservation,
+        has_one = opened_by,
+        close = opened_by,
+        seeds = [b"dispute".as_ref(), reservation.key().as_ref()],
+        bump = dispute.bump
+    )]
+    pub dispute: Account<'info, Dispute>,
+    /// CHECK: validado con has_one en la disputa; recupera su renta
+    #[account(mut)]
+    pub opened_by: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    // Solo necesario si la estancia cruza el fin de año
+    #[account(mut)]
+    pub next_calendar: Option<AccountLoader<'info, AvailabilityCalendar>>,
+    #[account(
+        mut,
+        seeds = [b"reservation-vault".as_ref(), reservation.key().as_ref()],
+        bump = reservation.vault_bump
+    )]
+    pub escrow_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = user_staking_account.owner == reservation.user)]
+    pub user_staking_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = owner_account.key() == reservation.payout_account @ ErrorCode::InvalidPayoutAccount)]
+    pub owner_account: InterfaceAccount<'info, TokenAccount>,
+    // Solo en reservas de grupo: el reembolso se reparte entre los huéspedes, cuyas
+    // cuentas de token van en `remaining_accounts` en el orden de las partes
+    #[account(
+        seeds = [b"group-booking".as_ref(), reservation.key().as_ref()],
+        bump = group_booking.bump
+    )]
+    pub group_booking: Option<Box<Account<'info, GroupBooking>>>,
+    pub settlement: Settlement<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitStakingPool<'info> {
+    #[account(seeds = [b"config".as_ref()], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 32 + 8 + 16 + 1,
+        seeds = [b"staking-pool".as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+    // Vault con las recompensas pendientes de repartir
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"reward-vault".as_ref(), mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = staking_pool,
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct DepositRewards<'info> {
+    #[account(seeds = [b"config".as_ref()], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"staking-pool".as_ref(), staking_pool.mint.as_ref()],
+        bump = staking_pool.bump,
+        has_one = reward_vault
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+    #[account(mut)]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = admin_token_account.owner == admin.key())]
+    pub admin_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = staking_pool.mint @ ErrorCode::InvalidMint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Cuentas para liquidar pagos: comisiones, referido y rendimiento del staking
+#[derive(Accounts)]
+pub struct Settlement<'info> {
+    #[account(seeds = [b"config".as_ref()], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [b"staking-pool".as_ref(), staking_pool.mint.as_ref()],
+        bump = staking_pool.bump,
+        has_one = reward_vault
+    )]
+    pub staking_pool: Box<Account<'info, StakingPool>>,
+    #[account(address = staking_pool.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = treasury_account.owner == config.treasury @ ErrorCode::InvalidTreasuryAccount)]
+    pub treasury_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Solo si la reserva tiene referido
+    #[account(mut)]
+    pub referrer_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    // Solo si la propiedad reparte parte de los pagos con un gestor
+    #[account(mut)]
+    pub delegate_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+}
+
+#[derive(Accounts)]
+pub struct GrantRole<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + 32 + 32 + 1 + 1 + 1 + 2 + 32 + 1,
+        seeds = [b"property-role".as_ref(), property.key().as_ref(), member.key().as_ref()],
+        bump
+    )]
+    pub property_role: Account<'info, PropertyRole>,
+    #[account(mut, has_one = owner)]
+    pub property: Account<'info, Property>,
+    /// CHECK: solo se usa su clave como miembro del equipo
+    pub member: UncheckedAccount<'info>,
+    // Solo si el miembro recibe parte de los pagos
+    #[account(constraint = payout_account.mint == property.mint @ ErrorCode::InvalidMint)]
+    pub payout_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeRole<'info> {
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"property-role".as_ref(), property.key().as_ref(), property_role.member.as_ref()],
+        bump = property_role.bump
+    )]
+    pub property_role: Account<'info, PropertyRole>,
+    #[account(mut, has_one = owner)]
+    pub property: Account<'info, Property>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddAcceptedMint<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + 32 + 32 + 1 + 32 + 32 + 32 + 8 + 2 + 1,
+        seeds = [b"accepted-mint".as_ref(), property.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub accepted_mint: Account<'info, AcceptedMint>,
+    #[account(has_one = owner)]
+    pub property: Account<'info, Property>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: validado en oracle::load_price
+    pub price_feed: UncheckedAccount<'info>,
+    #[account(constraint = payout_account.mint == mint.key() @ ErrorCode::InvalidMint)]
+    pub payout_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveAcceptedMint<'info> {
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"accepted-mint".as_ref(), property.key().as_ref(), accepted_mint.mint.as_ref()],
+        bump = accepted_mint.bump
+    )]
+    pub accepted_mint: Account<'info, AcceptedMint>,
+    #[account(has_one = owner)]
+    pub property: Account<'info, Property>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitPriceFeed<'info> {
+    #[account(init, payer = authority, space = 8 + 32 + 8 + 4 + 8 + 8)]
+    pub price_feed: Account<'info, PriceFeed>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPrice<'info> {
+    #[account(mut, has_one = authority)]
+    pub price_feed: Account<'info, PriceFeed>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(year: u16)]
+pub struct InitCalendar<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<AvailabilityCalendar>(),
+        seeds = [b"calendar".as_ref(), property.key().as_ref(), year.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    pub property: Account<'info, Property>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(start_date: i64)]
+pub struct BlockDates<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 8 + 8 + 1 + 32 + 1,
+        seeds = [b"date-block".as_ref(), property.key().as_ref(), start_date.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub date_block: Account<'info, DateBlock>,
+    pub property: Account<'info, Property>,
+    // El propietario o un miembro con permiso para bloquear fechas
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"property-role".as_ref(), property.key().as_ref(), authority.key().as_ref()],
+        bump = role.bump
+    )]
+    pub role: Option<Account<'info, PropertyRole>>,
+    #[account(mut)]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    #[account(mut)]
+    pub next_calendar: Option<AccountLoader<'info, AvailabilityCalendar>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnblockDates<'info> {
+    #[account(
+        mut,
+        has_one = property,
+        has_one = payer,
+        close = payer,
+        seeds = [b"date-block".as_ref(), property.key().as_ref(), date_block.start_date.to_le_bytes().as_ref()],
+        bump = date_block.bump
+    )]
+    pub date_block: Account<'info, DateBlock>,
+    pub property: Account<'info, Property>,
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"property-role".as_ref(), property.key().as_ref(), authority.key().as_ref()],
+        bump = role.bump
+    )]
+    pub role: Option<Account<'info, PropertyRole>>,
+    /// CHECK: quien pagó el bloqueo recupera la renta
+    #[account(mut)]
+    pub payer: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub calendar: AccountLoader<'info, AvailabilityCalendar>,
+    #[account(mut)]
+    pub next_calendar: Option<AccountLoader<'info, AvailabilityCalendar>>,
+}
+
+#[account]
+pub struct Reservation {
+    pub user: Pubkey,
+    pub property: Pubkey,
+    pub amount: u64,
+    pub check_in_date: i64,
+    pub check_out_date: i64,
+    pub status: ReservationStatus,
+    // Parte del importe ya pagada al propietario
+    pub released_amount: u64,
+    // Fianza retenida en el escrow junto al importe de la reserva
+    pub deposit: u64,
+    pub damage_claim: u64,
+    pub evidence_hash: [u8; 32],
+    pub checked_out_at: i64,
+    pub deposit_released: bool,
+    // Importe en el pool de staking y el índice de recompensas al entrar
+    pub staked_amount: u64,
+    pub reward_index_snapshot: u128,
+    // Rendimiento generado antes de un cambio en el importe en staking
+    pub pending_yield: u64,
+    // Pubkey::default() si no hay referido
+    pub referrer: Pubkey,
+    // Límite para aprobar una solicitud de reserva (0 en reserva inmediata)
+    pub approval_deadline: i64,
+    // Token único que da derecho a usar la reserva
+    pub pass_mint: Pubkey,
+    // Cuenta del propietario en el token con el que se pagó
+    pub payout_account: Pubkey,
+    pub guests: u8,
+    // Pagada a partes por varios huéspedes; los reembolsos se reparten entre ellos
+    pub is_group: bool,
+    // Tramos de reembolso vigentes al reservar; vacío si la propiedad no tenía política
+    pub refund_tiers: Vec<RefundTier>,
+    // Parte del importe que se paga en el check-in, fijada al reservar
+    pub check_in_payout_bps: u16,
+    // Plazo para reclamar daños tras el check-out, fijado al reservar
+    pub damage_claim_window: i64,
+    // Parte del importe que cobra el propietario si el huésped no se presenta
+    pub no_show_payout_bps: u16,
+    // Ventana de check-in fijada al reservar
+    pub check_in_opens_at: i64,
+    pub check_in_closes_at: i64,
+    pub pass_bump: u8,
+    // Tiene una oferta de reventa abierta con el pase depositado
+    pub listed: bool,
+    pub vault_bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ReservationStatus {
+    Booked,
+    CheckedIn,
+    CheckedOut,
+    Cancelled,
+    Disputed,
+    Resolved,
+    Pending,
+    Declined,
+    Expired,
+    // Reserva de grupo esperando las aportaciones de los huéspedes
+    Funding,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum BookingMode {
+    Instant,
+    RequestToBook,
+}
+
+// El propietario tiene siempre todos los permisos y no necesita rol
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Manager,
+    Cleaner,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum BlockReason {
+    Maintenance,
+    PersonalUse,
+    Other,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    ApproveBookings,
+    BlockDates,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyStatus {
+    Active,
+    Inactive,
+}
+
+#[account]
+pub struct Property {
+    pub owner: Pubkey,
+    pub property_id: u64,
+    pub payout_account: Pubkey,
+    // Token en el que se fijan los precios; sus decimales sirven para convertir a otros tokens
+    pub mint: Pubkey,
+    pub quote_decimals: u8,
+    pub nightly_rate: u64,
+    pub cleaning_fee: u64,
+    // Si tiene calendario de tarifas, que sustituye a la tarifa fija por noche
+    pub has_rate_schedule: bool,
+    // Desplazamientos respecto a la fecha de check-in entre los que se admite el check-in;
+    // el primero puede ser negativo para permitir llegadas anticipadas
+    pub check_in_earliest_offset: i64,
+    pub check_in_latest_offset: i64,
+    // Huso horario local de la propiedad en segundos, solo informativo
+    pub utc_offset: i32,
+    // Parte del pago que se libera al hacer check-in
+    pub check_in_payout_bps: u16,
+    // Segundos tras el check-out antes de poder liberar el resto sin el huésped
+    pub payout_delay: i64,
+    pub security_deposit: u64,
+    // Segundos tras el check-out en los que el propietario puede reclamar daños
+    pub damage_claim_window: i64,
+    // Parte del importe que cobra el propietario si el huésped no se presenta
+    pub no_show_payout_bps: u16,
+    pub allow_modifications: bool,
+    // Antelación mínima respecto al check-in para cambiar fechas
+    pub modification_cutoff: i64,
+    pub booking_mode: BookingMode,
+    // Segundos que tiene el propietario para responder a una solicitud
+    pub approval_timeout: i64,
+    // Si los pases de reserva se pueden regalar o revender
+    pub transferable: bool,
+    // Comisión del propietario en cada reventa y precio máximo respecto al original
+    pub resale_royalty_bps: u16,
+    pub resale_price_cap_bps: u16,
+    // Miembro del equipo que cobra una parte de los pagos al propietario y su cuenta en
+    // el token de la propiedad; en los tokens aceptados cobra en cualquier cuenta suya.
+    // Solo puede haber uno a la vez (PayoutShareTaken)
+    pub delegate: Pubkey,
+    pub delegate_payout_account: Pubkey,
+    pub delegate_payout_bps: u16,
+    // Reglas de reserva: duración de la estancia, huéspedes y antelación en segundos
+    pub min_nights: u16,
+    pub max_nights: u16,
+    pub max_guests: u8,
+    pub min_lead_time: i64,
+    pub max_booking_horizon: i64,
+    pub status: PropertyStatus,
+    pub bump: u8,
+}
+
+#[account]
+pub struct Config {
+    pub admin: Pubkey,
+    // Quien resuelve las disputas entre huésped y propietario
+    pub arbiter: Pubkey,
+    // Dueño de las cuentas que reciben la parte de la plataforma
+    pub treasury: Pubkey,
+    pub yield_guest_bps: u16,
+    pub yield_owner_bps: u16,
+    pub platform_fee_bps: u16,
+    pub referral_fee_bps: u16,
+    pub bump: u8,
+}
+
+#[account]
+pub struct ApprovedReferrer {
+    pub referrer: Pubkey,
+    pub bump: u8,
+}
+
+impl Config {
+    // Reparto del rendimiento en (huésped, propietario, plataforma)
+    pub fn yield_shares(&self, accrued: u64) -> (u64, u64, u64) {
+        let guest = apply_bps(accrued, self.yield_guest_bps);
+        let owner = apply_bps(accrued, self.yield_owner_bps);
+        (guest, owner, accrued - guest - owner)
+    }
+}
+
+#[account]
+pub struct StakingPool {
+    pub mint: Pubkey,
+    pub reward_vault: Pubkey,
+    pub total_staked: u64,
+    // Recompensa acumulada por unidad en staking, escalada por REWARD_INDEX_PRECISION
+    pub reward_index: u128,
+    pub bump: u8,
+}
+
+impl<'info> Settlement<'info> {
+    // Paga al propietario descontando la comisión de la plataforma y la del referido
+    pub fn pay_owner(
+        &self,
+        reservation: &Account<'info, Reservation>,
+        property: &Property,
+        escrow_vault: AccountInfo<'info>,
+        owner_account: AccountInfo<'info>,
+        amount: u64,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        let platform_fee = apply_bps(amount, self.config.platform_fee_bps);
+        let referral_fee = if reservation.referrer == Pubkey::default() {
+            0
+        } else {
+            apply_bps(amount, self.config.referral_fee_bps)
+        };
+        let mut owner_amount = amount - platform_fee - referral_fee;
+        // La parte del gestor sale de lo que cobra el propietario, en cualquier token
+        let delegate_share = apply_bps(owner_amount, property.delegate_payout_bps);
+        owner_amount -= delegate_share;
+
+        let mut payouts = vec![
+            (owner_account, owner_amount),
+            (self.treasury_account.to_account_info(), platform_fee),
+        ];
+        if delegate_share > 0 {
+            let delegate_account = self
+                .delegate_account
+                .as_ref()
+                .ok_or(ErrorCode::InvalidPayoutAccount)?;
+            let valid = if self.mint.key() == property.mint {
+                delegate_account.key() == property.delegate_payout_account
+            } else {
+                delegate_account.owner == property.delegate && delegate_account.mint == self.mint.key()
+            };
+            require!(valid, ErrorCode::InvalidPayoutAccount);
+            payouts.push((delegate_account.to_account_info(), delegate_share));
+        }
+        if referral_fee > 0 {
+            let referrer_account = self
+                .referrer_account
+                .as_ref()
+                .ok_or(ErrorCode::InvalidReferrer)?;
+            require_keys_eq!(referrer_account.owner, reservation.referrer, ErrorCode::InvalidReferrer);
+            payouts.push((referrer_account.to_account_info(), referral_fee));
+        }
+        for (to, amount) in payouts {
+            if amount > 0 {
+                admin::transfer_from_escrow(
+                    escrow_vault.clone(),
+                    to,
+                    &self.mint,
+                    &reservation.key(),
+                    reservation.vault_bump,
+                    amount,
+                    token_program.clone(),
+                )?;
+            }
+        }
+
+        emit!(PayoutReleasedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            owner_amount,
+            delegate_share,
+            platform_fee,
+            referrer: reservation.referrer,
+            referral_fee,
+            label: "payoutreleased".to_string()
+        });
+
+        Ok(())
+    }
+
+    // Saca la reserva del pool y reparte lo que ha generado
+    pub fn settle_yield(
+        &mut self,
+        reservation: &mut Account<'info, Reservation>,
+        mint: &Pubkey,
+        guest_account: AccountInfo<'info>,
+        owner_account: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        require_keys_eq!(self.staking_pool.mint, *mint, ErrorCode::InvalidMint);
+
+        let accrued = staking::unstake(&mut self.staking_pool, reservation)?;
+        if accrued == 0 {
+            return Ok(());
+        }
+        let (guest, owner, platform) = self.config.yield_shares(accrued);
+        for (to, amount) in [
+            (guest_account, guest),
+            (owner_account, owner),
+            (self.treasury_account.to_account_info(), platform),
+        ] {
+            if amount > 0 {
+                admin::transfer_from_pool(
+                    self.reward_vault.to_account_info(),
+                    to,
+                    self.staking_pool.to_account_info(),
+                    &self.mint,
+                    self.staking_pool.bump,
+                    amount,
+                    token_program.clone(),
+                )?;
+            }
+        }
+
+        emit!(YieldDistributedEvent {
+            reservation: reservation.key(),
+            property: reservation.property,
+            guest_share: guest,
+            owner_share: owner,
+            platform_share: platform,
+            label: "yielddistributed".to_string()
+        });
+
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Dispute {
+    pub reservation: Pubkey,
+    pub opened_by: Pubkey,
+    // Estado de la reserva al abrirse la disputa
+    pub previous_status: ReservationStatus,
+    pub reason_hash: [u8; 32],
+    pub opened_at: i64,
+    pub resolved_at: i64,
+    pub guest_amount: u64,
+    pub owner_amount: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct AcceptedMint {
+    pub property: Pubkey,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    // Cuenta de precios de Pyth y el feed que debe contener
+    pub price_feed: Pubkey,
+    pub feed_id: [u8; 32],
+    pub payout_account: Pubkey,
+    // Antigüedad máxima del precio en segundos y amplitud máxima del intervalo de confianza
+    pub max_price_age: i64,
+    pub max_confidence_bps: u16,
+    pub bump: u8,
+}
+
+// Oráculo simulado: solo se acepta como precio al compilar con `mock-oracle`
+#[account]
+pub struct PriceFeed {
+    pub authority: Pubkey,
+    pub price: i64,
+    pub expo: i32,
+    pub conf: u64,
+    pub publish_time: i64,
+}
+
+#[account]
+pub struct PropertyRole {
+    pub property: Pubkey,
+    pub member: Pubkey,
+    pub role: Role,
+    pub can_approve_bookings: bool,
+    pub can_block_dates: bool,
+    pub payout_share_bps: u16,
+    pub payout_account: Pubkey,
+    pub bump: u8,
+}
+
+impl PropertyRole {
+    pub fn allows(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::ApproveBookings => self.can_approve_bookings,
+            Permission::BlockDates => self.can_block_dates,
+        }
+    }
+}
+
+#[account]
+pub struct Listing {
+    pub reservation: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub bump: u8,
+    pub vault_bump: u8,
+}
+
+#[account]
+pub struct GroupBooking {
+    pub reservation: Pubkey,
+    pub organizer: Pubkey,
+    // Si no se ha financiado antes de este momento se devuelven las aportaciones
+    pub funding_deadline: i64,
+    pub funded_amount: u64,
+    pub shares: Vec<GuestShare>,
+    pub bump: u8,
+}
+
+impl GroupBooking {
+    // Reparte un reembolso según lo que aportó cada huésped; el último se queda con
+    // el resto del redondeo para no dejar saldo en el escrow
+    pub fn refund_parts(&self, amount: u64) -> Result<Vec<u64>> {
+        require!(self.funded_amount > 0, ErrorCode::InvalidGroupShares);
+        let mut remaining = amount;
+        let mut parts = Vec::with_capacity(self.shares.len());
+        for (index, share) in self.shares.iter().enumerate() {
+            let part = if index + 1 == self.shares.len() {
+                remaining
+            } else {
+                let part = (amount as u128) * (share.received as u128)
+                    / (self.funded_amount as u128);
+                part as u64
+            };
+            remaining = remaining.checked_sub(part).ok_or(ErrorCode::MathOverflow)?;
+            parts.push(part);
+        }
+        Ok(parts)
+    }
+}
+
+// Parte de un huésped tal como la fija el organizador al crear la reserva
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct GuestShareInput {
+    pub guest: Pubkey,
+    pub amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct GuestShare {
+    pub guest: Pubkey,
+    pub amount: u64,
+    pub paid: bool,
+    // Lo que llegó al escrow, descontadas las comisiones de transferencia
+    pub received: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RefundTier {
+    // Segundos mínimos de antelación respecto al check-in
+    pub min_notice: i64,
+    pub refund_bps: u16,
+}
+
+// Tarifa por noche para un periodo concreto (temporada alta, festivos...)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RateOverride {
+    pub start_date: i64,
+    pub end_date: i64,
+    pub nightly_rate: u64,
+}
 
-        // Colocar los fondos en staking
-        admin::stake_tokens(
-            ctx.accounts.user_staking_account.to_account_info(),
-            ctx.accounts.admin_staking_account.to_account_info(),
-            ctx.accounts.user.to_account_info(),
-            amount,
-            ctx.accounts.token_program.to_account_info(),
-        )?;
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct StayDiscount {
+    pub min_nights: u16,
+    pub discount_bps: u16,
+}
 
-        Ok(())
+#[account]
+pub struct RateSchedule {
+    pub owner: Pubkey,
+    pub property: Pubkey,
+    // Multiplicador de la tarifa según el día de la semana, de lunes a domingo
+    pub weekday_multipliers_bps: [u16; 7],
+    pub overrides: Vec<RateOverride>,
+    pub stay_discounts: Vec<StayDiscount>,
+    pub bump: u8,
+}
+
+#[account]
+pub struct CancellationPolicy {
+    pub owner: Pubkey,
+    pub property: Pubkey,
+    pub tiers: Vec<RefundTier>,
+    pub bump: u8,
+}
+
+// Un bit por noche del año natural (366 noches como máximo)
+#[account(zero_copy)]
+pub struct AvailabilityCalendar {
+    pub property: Pubkey,
+    pub booked: [u8; 48],
+    pub year: u16,
+    pub bump: u8,
+    pub _padding: [u8; 1],
+}
+
+impl AvailabilityCalendar {
+    pub fn is_booked(&self, ordinal: usize) -> bool {
+        self.booked[ordinal / 8] & (1 << (ordinal % 8)) != 0
     }
 
-    pub fn check_in(ctx: Context<CheckIn>) -> Result<()> {
-        let now = Clock::get()?.unix_timestamp;
-        // Permitir check-in en las siguientes 48 horas
+    pub fn set_booked(&mut self, ordinal: usize, booked: bool) {
+        if booked {
+            self.booked[ordinal / 8] |= 1 << (ordinal % 8);
+        } else {
+            self.booked[ordinal / 8] &= !(1 << (ordinal % 8));
+        }
+    }
+}
+
+// Noches retiradas de la venta por el propietario, marcadas también en el calendario
+#[account]
+pub struct DateBlock {
+    pub property: Pubkey,
+    pub start_date: i64,
+    pub end_date: i64,
+    pub reason: BlockReason,
+    pub payer: Pubkey,
+    pub bump: u8,
+}
+
+impl Reservation {
+    // Estados en los que ya no queda nada por pagar ni devolver
+    pub fn is_settled(&self) -> bool {
+        match self.status {
+            ReservationStatus::CheckedOut => self.deposit_released,
+            ReservationStatus::Cancelled
+            | ReservationStatus::Resolved
+            | ReservationStatus::Declined
+            | ReservationStatus::Expired => true,
+            _ => false,
+        }
+    }
+
+    // Retirar una solicitud aún no aprobada se reembolsa entera; si no, el primer
+    // tramo cuya antelación mínima se supera, y sin tramo no hay reembolso
+    pub fn refund_bps(&self, now: i64) -> u16 {
+        if self.status == ReservationStatus::Pending {
+            return BPS_DENOMINATOR as u16;
+        }
+        let notice = self.check_in_date.saturating_sub(now);
+        self.refund_tiers
+            .iter()
+            .find(|tier| notice > tier.min_notice)
+            .map(|tier| tier.refund_bps)
+            .unwrap_or(0)
+    }
+}
+
+impl Property {
+    // El propietario puede todo; el resto necesita un rol con el permiso
+    pub fn authorize(
+        &self,
+        authority: &Pubkey,
+        role: Option<&PropertyRole>,
+        permission: Permission,
+    ) -> Result<()> {
+        if *authority == self.owner {
+            return Ok(());
+        }
         require!(
-            now >= ctx.accounts.reservation.check_in_date
-                && now <= ctx.accounts.reservation.check_in_date + 172800, // 48 horas
-            ErrorCode::InvalidCheckInDate
+            role.is_some_and(|role| role.allows(permission)),
+            ErrorCode::Unauthorized
         );
+        Ok(())
+    }
 
-        let reservation = &mut ctx.accounts.reservation;
-        require!(!reservation.is_paid, ErrorCode::AlreadyPaid);
+    // Precio de las noches según el calendario de tarifas o la tarifa fija, más la limpieza
+    pub fn quote(
+        &self,
+        rate_schedule: Option<&RateSchedule>,
+        check_in_date: i64,
+        check_out_date: i64,
+    ) -> Result<u64> {
+        let nights = calendar::nights(check_in_date, check_out_date);
+        require!(nights > 0, ErrorCode::InvalidCheckOutDate);
 
-        // Transferir fondos de la cuenta de staking del administrador a la cuenta del propietario
-        admin::transfer_from_staking(
-            ctx.accounts.admin_staking_account.to_account_info(),
-            ctx.accounts.owner_account.to_account_info(),
-            ctx.accounts.admin_authority.to_account_info(),
-            reservation.amount,
-            ctx.accounts.token_program.to_account_info(),
-        )?;
+        let stay = match rate_schedule {
+            Some(schedule) => schedule.stay_price(self.nightly_rate, check_in_date, check_out_date)?,
+            None => {
+                require!(!self.has_rate_schedule, ErrorCode::RateScheduleRequired);
+                self.nightly_rate
+                    .checked_mul(nights as u64)
+                    .ok_or(ErrorCode::MathOverflow)?
+            }
+        };
+        stay.checked_add(self.cleaning_fee)
+            .ok_or_else(|| error!(ErrorCode::MathOverflow))
+    }
 
-        // Actualizar el estado de la reserva
-        reservation.is_paid = true;
+    pub fn check_booking_rules(
+        &self,
+        now: i64,
+        check_in_date: i64,
+        check_out_date: i64,
+        guests: u8,
+    ) -> Result<()> {
+        let nights = calendar::nights(check_in_date, check_out_date);
+        require!(nights >= self.min_nights as i64, ErrorCode::StayTooShort);
+        require!(nights <= self.max_nights as i64, ErrorCode::StayTooLong);
+        require!(guests > 0, ErrorCode::InvalidGuestCount);
+        require!(guests <= self.max_guests, ErrorCode::TooManyGuests);
+        // También impide reservar fechas pasadas
+        require!(
+            check_in_date >= now.saturating_add(self.min_lead_time),
+            ErrorCode::InsufficientLeadTime
+        );
+        require!(
+            check_in_date <= now.saturating_add(self.max_booking_horizon),
+            ErrorCode::BeyondBookingHorizon
+        );
         Ok(())
     }
-}
 
+    // Estado de una reserva pagada: con solicitud de reserva el propietario
+    // tiene que aprobarla antes del plazo, que nunca pasa de la apertura del check-in
+    pub fn confirmed_status(&self, now: i64, check_in_opens_at: i64) -> (ReservationStatus, i64) {
+        match self.booking_mode {
+            BookingMode::Instant => (ReservationStatus::Booked, 0),
+            BookingMode::RequestToBook => {
+                let deadline = now.saturating_add(self.approval_timeout);
+                (ReservationStatus::Pending, deadline.min(check_in_opens_at))
+            }
+        }
+    }
 
-#[derive(Accounts)]
-pub struct CreateReservation<'info> {
-    #[account(init, payer = user, space = 8 + 32 + 8 + 8 + 1)]
-    pub reservation: Account<'info, Reservation>,
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(mut)]
-    pub user_staking_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub admin_staking_account: Account<'info, TokenAccount>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    pub fn check_in_opens_at(&self, check_in_date: i64) -> i64 {
+        check_in_date.saturating_add(self.check_in_earliest_offset)
+    }
+
+    pub fn check_in_closes_at(&self, check_in_date: i64) -> i64 {
+        check_in_date.saturating_add(self.check_in_latest_offset)
+    }
 }
 
+impl AcceptedMint {
+    // Convierte un importe en la moneda de la propiedad a unidades del token,
+    // redondeando a favor del propietario
+    pub fn to_tokens(
+        &self,
+        price_feed: &oracle::OraclePrice,
+        quote_amount: u64,
+        quote_decimals: u8,
+        now: i64,
+    ) -> Result<u64> {
+        require!(price_feed.price > 0, ErrorCode::InvalidPriceFeed);
+        require!(
+            now.saturating_sub(price_feed.publish_time) <= self.max_price_age,
+            ErrorCode::StalePrice
+        );
+        let price = price_feed.price as u128;
+        require!(
+            (price_feed.conf as u128) * (BPS_DENOMINATOR as u128)
+                <= price * (self.max_confidence_bps as u128),
+            ErrorCode::PriceConfidenceTooLow
+        );
+
+        let exponent = self.decimals as i32 - quote_decimals as i32 - price_feed.expo;
+        let scale = 10u128
+            .checked_pow(exponent.unsigned_abs())
+            .ok_or(ErrorCode::MathOverflow)?;
+        let (numerator, denominator) = if exponent >= 0 {
+            ((quote_amount as u128).checked_mul(scale), Some(price))
+        } else {
+            (Some(quote_amount as u128), price.checked_mul(scale))
+        };
+        let (numerator, denominator) = numerator
+            .zip(denominator)
+            .ok_or(ErrorCode::MathOverflow)?;
 
-#[derive(Accounts)]
-pub struct CheckIn<'info> {
-    #[account(mut, has_one = user)]
-    pub reservation: Account<'info, Reservation>,
-    pub user: Signer<'info>,
-    #[account(mut)]
-    pub admin_staking_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub owner_account: Account<'info, TokenAccount>,
-    pub admin_authority: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+        u64::try_from(numerator.div_ceil(denominator)).map_err(|_| error!(ErrorCode::MathOverflow))
+    }
 }
 
-#[account]
-pub struct Reservation {
-    pub user: Pubkey,
-    pub amount: u64,
-    pub check_in_date: i64,
-    pub is_paid: bool,
+impl RateSchedule {
+    // Suma noche a noche la tarifa del periodo (o la base) por el multiplicador del
+    // día de la semana y aplica el mayor descuento por duración alcanzado
+    pub fn stay_price(&self, base_rate: u64, check_in_date: i64, check_out_date: i64) -> Result<u64> {
+        let first_night = check_in_date.div_euclid(calendar::SECONDS_PER_DAY);
+        let last_night = check_out_date.div_euclid(calendar::SECONDS_PER_DAY);
+
+        let mut total: u64 = 0;
+        for night in first_night..last_night {
+            let night_start = night * calendar::SECONDS_PER_DAY;
+            let rate = self
+                .overrides
+                .iter()
+                .find(|rate_override| {
+                    night_start >= rate_override.start_date && night_start < rate_override.end_date
+                })
+                .map(|rate_override| rate_override.nightly_rate)
+                .unwrap_or(base_rate);
+            let rate = apply_bps(rate, self.weekday_multipliers_bps[calendar::weekday(night)]);
+            total = total.checked_add(rate).ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        let nights = (last_night - first_night) as u64;
+        let discount_bps = self
+            .stay_discounts
+            .iter()
+            .rev()
+            .find(|discount| nights >= discount.min_nights as u64)
+            .map(|discount| discount.discount_bps)
+            .unwrap_or(0);
+        Ok(total - apply_bps(total, discount_bps))
+    }
 }
 
 #[error_code]
@@ -104,6 +4013,426 @@ pub enum ErrorCode {
     InvalidCheckInDate,
     #[msg("La reserva ya ha sido pagada.")]
     AlreadyPaid,
+    #[msg("La reserva ha sido cancelada.")]
+    ReservationCancelled,
+    #[msg("La política de cancelación no es válida.")]
+    InvalidRefundPolicy,
+    #[msg("La propiedad no está activa.")]
+    PropertyInactive,
+    #[msg("La configuración de la propiedad no es válida.")]
+    InvalidPropertyConfig,
+    #[msg("La cuenta de pago no es la registrada para la propiedad.")]
+    InvalidPayoutAccount,
+    #[msg("El token no coincide con el de la propiedad.")]
+    InvalidMint,
+    #[msg("Alguna de las noches solicitadas ya está reservada.")]
+    NightsUnavailable,
+    #[msg("El calendario no corresponde a la propiedad o a las fechas.")]
+    CalendarMismatch,
+    #[msg("La fecha de check-out no es válida.")]
+    InvalidCheckOutDate,
+    #[msg("El importe no coincide con el precio de la estancia.")]
+    AmountMismatch,
+    #[msg("Desbordamiento aritmético.")]
+    MathOverflow,
+    #[msg("La reserva no está en el estado requerido.")]
+    InvalidReservationStatus,
+    #[msg("Todavía no se puede liberar el pago.")]
+    PayoutNotYetAvailable,
+    #[msg("El plazo para reclamar daños ha terminado.")]
+    DamageClaimWindowClosed,
+    #[msg("El plazo para reclamar daños sigue abierto.")]
+    DamageClaimWindowOpen,
+    #[msg("La reclamación de daños no es válida.")]
+    InvalidDamageClaim,
+    #[msg("La fianza ya ha sido liberada.")]
+    DepositAlreadyReleased,
+    #[msg("Solo el huésped o el propietario pueden abrir una disputa.")]
+    NotReservationParty,
+    #[msg("El reparto supera los fondos del escrow.")]
+    InvalidDisputeSplit,
+    #[msg("El plazo de check-in sigue abierto.")]
+    NoShowWindowOpen,
+    #[msg("El reparto del rendimiento no es válido.")]
+    InvalidYieldSplit,
+    #[msg("No hay fondos en staking para repartir recompensas.")]
+    NothingStaked,
+    #[msg("La cuenta no pertenece a la tesorería.")]
+    InvalidTreasuryAccount,
+    #[msg("La configuración de comisiones no es válida.")]
+    InvalidFeeConfig,
+    #[msg("El referido no es válido.")]
+    InvalidReferrer,
+    #[msg("La propiedad no admite cambios en la reserva.")]
+    ModificationNotAllowed,
+    #[msg("La reserva está pendiente de aprobación.")]
+    ReservationPending,
+    #[msg("La solicitud de reserva ha caducado.")]
+    RequestExpired,
+    #[msg("La solicitud de reserva todavía no ha caducado.")]
+    RequestNotExpired,
+    #[msg("No tienes permiso para esta operación.")]
+    Unauthorized,
+    #[msg("La reserva todavía no está liquidada.")]
+    ReservationNotSettled,
+    #[msg("El escrow de la reserva no está vacío.")]
+    EscrowNotEmpty,
+    #[msg("Las cuentas adicionales no son válidas.")]
+    InvalidRemainingAccounts,
+    #[msg("El reparto de la reserva de grupo no es válido.")]
+    InvalidGroupShares,
+    #[msg("El plazo de financiación no es válido.")]
+    InvalidFundingDeadline,
+    #[msg("El plazo de financiación ha terminado.")]
+    FundingDeadlinePassed,
+    #[msg("El plazo de financiación sigue abierto.")]
+    FundingDeadlineOpen,
+    #[msg("No formas parte de esta reserva de grupo.")]
+    NotGroupGuest,
+    #[msg("Tu parte ya está pagada.")]
+    ShareAlreadyPaid,
+    #[msg("No tienes el pase de esta reserva.")]
+    NotPassHolder,
+    #[msg("La propiedad no admite la reventa de reservas.")]
+    ResaleNotAllowed,
+    #[msg("El precio de reventa supera el máximo permitido.")]
+    ResalePriceTooHigh,
+    #[msg("La propiedad no acepta pagos con este token.")]
+    MintNotAccepted,
+    #[msg("El oráculo de precios no es válido.")]
+    InvalidPriceFeed,
+    #[msg("El precio del oráculo está desactualizado.")]
+    StalePrice,
+    #[msg("El intervalo de confianza del precio es demasiado amplio.")]
+    PriceConfidenceTooLow,
+    #[msg("El calendario de tarifas no es válido.")]
+    InvalidRateSchedule,
+    #[msg("La propiedad exige su calendario de tarifas para calcular el precio.")]
+    RateScheduleRequired,
+    #[msg("El rol no es válido para este miembro.")]
+    InvalidRole,
+    #[msg("Otro miembro ya recibe una parte de los pagos; revoca su rol primero.")]
+    PayoutShareTaken,
+    #[msg("Las fechas del bloqueo no son válidas.")]
+    InvalidBlockDates,
+    #[msg("Lo recibido en el escrow no cubre la fianza.")]
+    DepositNotCovered,
+    #[msg("El pago supera el máximo aceptado por el huésped.")]
+    PaymentAboveMaximum,
+    #[msg("El oráculo simulado solo está disponible en pruebas.")]
+    MockOracleDisabled,
+    #[msg("Las reservas de grupo necesitan su cuenta de grupo y las de los huéspedes.")]
+    GroupBookingRequired,
+    #[msg("La estancia es más corta que el mínimo de noches.")]
+    StayTooShort,
+    #[msg("La estancia supera el máximo de noches.")]
+    StayTooLong,
+    #[msg("El número de huéspedes no es válido.")]
+    InvalidGuestCount,
+    #[msg("La reserva supera el máximo de huéspedes.")]
+    TooManyGuests,
+    #[msg("La reserva no respeta la antelación mínima.")]
+    InsufficientLeadTime,
+    #[msg("La fecha de entrada está demasiado lejos.")]
+    BeyondBookingHorizon,
+    #[msg("El plazo de check-in ha terminado y la reserva ya no se puede revender.")]
+    ResaleWindowClosed,
+    #[msg("Falta la oferta de reventa de la reserva.")]
+    ListingRequired,
+}
+
+pub mod staking {
+    use super::*;
+
+    pub const REWARD_INDEX_PRECISION: u128 = 1_000_000_000_000;
+
+    pub fn stake(pool: &mut StakingPool, reservation: &mut Reservation, amount: u64) -> Result<()> {
+        pool.total_staked = pool
+            .total_staked
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        reservation.staked_amount = amount;
+        reservation.reward_index_snapshot = pool.reward_index;
+        Ok(())
+    }
+
+    // Devuelve el rendimiento acumulado desde que la reserva entró en el pool
+    pub fn unstake(pool: &mut StakingPool, reservation: &mut Reservation) -> Result<u64> {
+        if reservation.staked_amount == 0 {
+            return Ok(0);
+        }
+
+        let accrued = accrued_since_snapshot(pool, reservation)? + reservation.pending_yield;
+        pool.total_staked -= reservation.staked_amount;
+        reservation.staked_amount = 0;
+        reservation.pending_yield = 0;
+        reservation.reward_index_snapshot = pool.reward_index;
+        Ok(accrued)
+    }
+
+    // Cambia el importe en staking guardando lo generado hasta ahora
+    pub fn restake(pool: &mut StakingPool, reservation: &mut Reservation, amount: u64) -> Result<()> {
+        reservation.pending_yield = reservation
+            .pending_yield
+            .checked_add(accrued_since_snapshot(pool, reservation)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        pool.total_staked = (pool.total_staked - reservation.staked_amount)
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        reservation.staked_amount = amount;
+        reservation.reward_index_snapshot = pool.reward_index;
+        Ok(())
+    }
+
+    fn accrued_since_snapshot(pool: &StakingPool, reservation: &Reservation) -> Result<u64> {
+        let accrued = (reservation.staked_amount as u128)
+            .checked_mul(pool.reward_index - reservation.reward_index_snapshot)
+            .ok_or(ErrorCode::MathOverflow)?
+            / REWARD_INDEX_PRECISION;
+        Ok(accrued as u64)
+    }
+
+    pub fn accrue_rewards(pool: &mut StakingPool, amount: u64) -> Result<()> {
+        let increment = (amount as u128)
+            .checked_mul(REWARD_INDEX_PRECISION)
+            .ok_or(ErrorCode::MathOverflow)?
+            / pool.total_staked as u128;
+        pool.reward_index = pool
+            .reward_index
+            .checked_add(increment)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+}
+
+// Lectura de precios de Pyth (cuentas PriceUpdateV2 del programa receptor)
+pub mod oracle {
+    use super::*;
+
+    pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+    pub const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
+    // Precio de una unidad entera del token en la moneda de la propiedad: price * 10^expo
+    pub struct OraclePrice {
+        pub price: i64,
+        pub expo: i32,
+        pub conf: u64,
+        pub publish_time: i64,
+    }
+
+    #[derive(AnchorDeserialize)]
+    pub enum VerificationLevel {
+        Partial { num_signatures: u8 },
+        Full,
+    }
+
+    #[derive(AnchorDeserialize)]
+    pub struct PriceFeedMessage {
+        pub feed_id: [u8; 32],
+        pub price: i64,
+        pub conf: u64,
+        pub exponent: i32,
+        pub publish_time: i64,
+        pub prev_publish_time: i64,
+        pub ema_price: i64,
+        pub ema_conf: u64,
+    }
+
+    #[derive(AnchorDeserialize)]
+    pub struct PriceUpdateV2 {
+        pub write_authority: Pubkey,
+        pub verification_level: VerificationLevel,
+        pub price_message: PriceFeedMessage,
+        pub posted_slot: u64,
+    }
+
+    // Solo acepta actualizaciones verificadas por completo del feed indicado
+    pub fn load_price(feed: &AccountInfo, feed_id: &[u8; 32]) -> Result<OraclePrice> {
+        #[cfg(feature = "mock-oracle")]
+        if *feed.owner == crate::ID {
+            let mock = PriceFeed::try_deserialize(&mut &feed.try_borrow_data()?[..])?;
+            return Ok(OraclePrice {
+                price: mock.price,
+                expo: mock.expo,
+                conf: mock.conf,
+                publish_time: mock.publish_time,
+            });
+        }
+
+        require_keys_eq!(*feed.owner, PYTH_RECEIVER_PROGRAM_ID, ErrorCode::InvalidPriceFeed);
+        let data = feed.try_borrow_data()?;
+        parse_price_update(&data, feed_id)
+    }
+
+    pub fn parse_price_update(data: &[u8], feed_id: &[u8; 32]) -> Result<OraclePrice> {
+        require!(
+            data.len() > 8 && data[..8] == PRICE_UPDATE_V2_DISCRIMINATOR,
+            ErrorCode::InvalidPriceFeed
+        );
+        let update = PriceUpdateV2::deserialize(&mut &data[8..])
+            .map_err(|_| error!(ErrorCode::InvalidPriceFeed))?;
+        require!(
+            matches!(update.verification_level, VerificationLevel::Full),
+            ErrorCode::InvalidPriceFeed
+        );
+        let message = update.price_message;
+        require!(message.feed_id == *feed_id, ErrorCode::InvalidPriceFeed);
+
+        Ok(OraclePrice {
+            price: message.price,
+            expo: message.exponent,
+            conf: message.conf,
+            publish_time: message.publish_time,
+        })
+    }
+}
+
+pub mod calendar {
+    use super::*;
+
+    pub const SECONDS_PER_DAY: i64 = 86400;
+
+    // El calendario del año de la estancia y, si cruza el fin de año, el del siguiente
+    pub fn stay_calendars<'a, 'info>(
+        calendar: &'a AccountLoader<'info, AvailabilityCalendar>,
+        next_calendar: &'a Option<AccountLoader<'info, AvailabilityCalendar>>,
+    ) -> Vec<&'a AccountLoader<'info, AvailabilityCalendar>> {
+        let mut calendars = vec![calendar];
+        calendars.extend(next_calendar.as_ref());
+        calendars
+    }
+
+    // Marca como reservadas las noches entre check-in y check-out; falla si alguna está ocupada
+    pub fn reserve_nights(
+        calendars: &[&AccountLoader<AvailabilityCalendar>],
+        property: &Pubkey,
+        check_in_date: i64,
+        check_out_date: i64,
+    ) -> Result<()> {
+        set_nights(calendars, property, check_in_date, check_out_date, true)
+    }
+
+    pub fn release_nights(
+        calendars: &[&AccountLoader<AvailabilityCalendar>],
+        property: &Pubkey,
+        check_in_date: i64,
+        check_out_date: i64,
+    ) -> Result<()> {
+        set_nights(calendars, property, check_in_date, check_out_date, false)
+    }
+
+    fn set_nights(
+        calendars: &[&AccountLoader<AvailabilityCalendar>],
+        property: &Pubkey,
+        check_in_date: i64,
+        check_out_date: i64,
+        booked: bool,
+    ) -> Result<()> {
+        let mut loaded = calendars
+            .iter()
+            .map(|calendar| calendar.load_mut())
+            .collect::<Result<Vec<_>>>()?;
+        for calendar in loaded.iter() {
+            require_keys_eq!(calendar.property, *property, ErrorCode::CalendarMismatch);
+        }
+
+        let first_night = check_in_date.div_euclid(SECONDS_PER_DAY);
+        let last_night = check_out_date.div_euclid(SECONDS_PER_DAY);
+        for night in first_night..last_night {
+            let (year, ordinal) = year_and_ordinal(night);
+            let calendar = loaded
+                .iter_mut()
+                .find(|calendar| calendar.year as i64 == year)
+                .ok_or(ErrorCode::CalendarMismatch)?;
+            if booked {
+                require!(!calendar.is_booked(ordinal), ErrorCode::NightsUnavailable);
+            }
+            calendar.set_booked(ordinal, booked);
+        }
+        Ok(())
+    }
+
+    // Noches entre las fechas de check-in y check-out
+    pub fn nights(check_in_date: i64, check_out_date: i64) -> i64 {
+        check_out_date.div_euclid(SECONDS_PER_DAY) - check_in_date.div_euclid(SECONDS_PER_DAY)
+    }
+
+    // Día de la semana, de 0 (lunes) a 6 (domingo); el 1970-01-01 fue jueves
+    pub fn weekday(day: i64) -> usize {
+        (day + 3).rem_euclid(7) as usize
+    }
+
+    // Año natural y día del año (desde 0) de un día contado desde 1970-01-01
+    pub fn year_and_ordinal(day: i64) -> (i64, usize) {
+        let year = civil_year(day);
+        (year, (day - days_from_civil(year, 1, 1)) as usize)
+    }
+
+    // Algoritmos de fechas civiles de Howard Hinnant
+    fn civil_year(day: i64) -> i64 {
+        let z = day + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        yoe + era * 400 + if month <= 2 { 1 } else { 0 }
+    }
+
+    fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+        let y = if month <= 2 { year - 1 } else { year };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn year_and_ordinal_handles_leap_years() {
+            // 2024-02-29 y 2024-12-31
+            assert_eq!(year_and_ordinal(19782), (2024, 59));
+            assert_eq!(year_and_ordinal(20088), (2024, 365));
+            // 2023-12-31: año no bisiesto
+            assert_eq!(year_and_ordinal(19722), (2023, 364));
+            // 2000 es bisiesto y 1900 no
+            assert_eq!(year_and_ordinal(11322), (2000, 365));
+            assert_eq!(year_and_ordinal(-25203), (1900, 364));
+        }
+
+        #[test]
+        fn year_and_ordinal_rolls_over_new_year() {
+            assert_eq!(year_and_ordinal(20088), (2024, 365));
+            assert_eq!(year_and_ordinal(20089), (2025, 0));
+            assert_eq!(year_and_ordinal(-1), (1969, 364));
+            assert_eq!(year_and_ordinal(0), (1970, 0));
+        }
+
+        #[test]
+        fn civil_year_matches_days_from_civil() {
+            for year in [1600, 1900, 1969, 1970, 2000, 2023, 2024, 2100] {
+                let first_day = days_from_civil(year, 1, 1);
+                assert_eq!(civil_year(first_day), year);
+                assert_eq!(civil_year(first_day - 1), year - 1);
+            }
+        }
+
+        #[test]
+        fn weekday_starts_on_monday() {
+            // 1970-01-01 fue jueves
+            assert_eq!(weekday(0), 3);
+            assert_eq!(weekday(-1), 2);
+            assert_eq!(weekday(4), 0);
+            // 2024-02-29 fue jueves
+            assert_eq!(weekday(19782), 3);
+        }
+    }
 }
 
 
@@ -111,22 +4440,29 @@ pub enum ErrorCode {
 
 pub mod admin {
     use anchor_lang::prelude::*;
-    use anchor_spl::token::{self, TokenAccount, Transfer, Token};
+    use anchor_spl::token::{self, Transfer};
+    use anchor_spl::token_2022::spl_token_2022::{
+        self,
+        extension::{transfer_fee::TransferFeeAmount, BaseStateWithExtensions, StateWithExtensions},
+        instruction::AuthorityType,
+    };
+    use anchor_spl::token_2022_extensions::transfer_fee;
     use super::*;
+    use super::ErrorCode;
 
     // Function to lock project tokens and mint derivatives
     pub fn lock_project_tokens(
         ctx: Context<TokenLock>,
         timestamp: String,
-        vault_bump: u8,
+        _vault_bump: u8,
         amount: u64
     ) -> Result<()> {
         // Validate timestamp
-        let now_ts = Clock::get()?.unix_timestamp as u64;
+        let _now_ts = Clock::get()?.unix_timestamp as u64;
         let date_ts = normalize_timestamp(&timestamp)?;
         
         // Uncomment for production to prevent locking tokens in the past
-        // require!(date_ts > now_ts, CustomError::CannotLockInPast);
+        // require!(date_ts > _now_ts, CustomError::CannotLockInPast);
 
         // Transfer project tokens to vault
         transfer_to_vault(&ctx, amount)?;
@@ -134,91 +4470,454 @@ pub mod admin {
         // Mint derivative tokens
         mint_derivative_tokens(&ctx, &timestamp, amount)?;
 
-        // Emit Token Lock Event
-        emit!(TokenLockEvent {
-            basetoken: ctx.accounts.basemint.key(),
-            amount,
-            derivativetoken: ctx.accounts.mint.key(),
-            receiver: ctx.accounts.der_ata.owner,
-            timestamp: date_ts.to_string(),
-            label: "tokenlock".to_string()
-        });
+        // Emit Token Lock Event
+        emit!(TokenLockEvent {
+            basetoken: ctx.accounts.basemint.key(),
+            amount,
+            derivativetoken: ctx.accounts.mint.key(),
+            receiver: ctx.accounts.der_ata.owner,
+            timestamp: date_ts.to_string(),
+            label: "tokenlock".to_string()
+        });
+
+        Ok(())
+    }
+
+    // Function to burn derivatives and unlock project tokens
+    pub fn unlock_project_tokens(
+        ctx: Context<TokenUnlock>,
+        timestamp: String,
+        vault_bump: u8,
+        amount: u64
+    ) -> Result<()> {
+        // Validate timestamp and check if unlock time has passed
+        let now_ts = Clock::get()?.unix_timestamp as u64;
+        let date_ts = normalize_timestamp(&timestamp)?;
+        require!(now_ts > date_ts, CustomError::UnlockTimeNotReached);
+
+        // Burn derivative tokens
+        burn_derivative_tokens(&ctx, &timestamp, amount)?;
+
+        // Transfer project tokens from vault to user
+        transfer_from_vault(&ctx, &timestamp, vault_bump, amount)?;
+
+        // Emit Token Unlock Event
+        emit!(TokenUnlockEvent {
+            basetoken: ctx.accounts.basemint.key(),
+            amount,
+            derivativetoken: ctx.accounts.mint.key(),
+            receiver: ctx.accounts.base_ata.owner,
+            timestamp: date_ts.to_string(),
+            label: "tokenunlock".to_string()
+        });
+
+        Ok(())
+    }
+
+
+    
+    #[allow(clippy::too_many_arguments)]
+    pub fn stake_tokens<'info>(
+        user_staking_account: AccountInfo<'info>,
+        escrow_vault: AccountInfo<'info>,
+        user: AccountInfo<'info>,
+        mint: &InterfaceAccount<'info, Mint>,
+        staking_pool: &mut StakingPool,
+        reservation: &mut Reservation,
+        amount: u64,
+        token_program: AccountInfo<'info>,
+    ) -> Result<u64> {
+        // Transferir tokens del usuario al escrow de la reserva
+        let received = transfer_in(
+            user_staking_account,
+            escrow_vault,
+            user,
+            mint,
+            amount,
+            token_program,
+        )?;
+
+        // Colocar los tokens en staking: el escrow empieza a generar recompensas del pool
+        staking::stake(staking_pool, reservation, received)?;
+
+        Ok(received)
+    }
+
+    // Transferencia firmada por el usuario; devuelve lo que llega realmente a `to`,
+    // que con la extensión de comisiones de Token-2022 es menos de lo enviado
+    pub fn transfer_in<'info>(
+        from: AccountInfo<'info>,
+        to: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        mint: &InterfaceAccount<'info, Mint>,
+        amount: u64,
+        token_program: AccountInfo<'info>,
+    ) -> Result<u64> {
+        let balance_before = token::accessor::amount(&to)?;
+
+        let cpi_accounts = TransferChecked {
+            from,
+            mint: mint.to_account_info(),
+            to: to.clone(),
+            authority,
+        };
+        let cpi_ctx = CpiContext::new(token_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)?;
+
+        let balance_after = token::accessor::amount(&to)?;
+        Ok(balance_after - balance_before)
+    }
+
+    // El escrow es su propia autoridad, así que firma con las seeds de la PDA
+    pub fn transfer_from_escrow<'info>(
+        escrow_vault: AccountInfo<'info>,
+        to: AccountInfo<'info>,
+        mint: &InterfaceAccount<'info, Mint>,
+        reservation: &Pubkey,
+        vault_bump: u8,
+        amount: u64,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        let seeds = &[
+            b"reservation-vault".as_ref(),
+            reservation.as_ref(),
+            &[vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: escrow_vault.clone(),
+            mint: mint.to_account_info(),
+            to: to.clone(),
+            authority: escrow_vault.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)
+    }
+
+    // El vault de recompensas lo controla la PDA del pool
+    pub fn transfer_from_pool<'info>(
+        reward_vault: AccountInfo<'info>,
+        to: AccountInfo<'info>,
+        staking_pool: AccountInfo<'info>,
+        mint: &InterfaceAccount<'info, Mint>,
+        pool_bump: u8,
+        amount: u64,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        let mint_key = mint.key();
+        let seeds = &[
+            b"staking-pool".as_ref(),
+            mint_key.as_ref(),
+            &[pool_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: reward_vault.clone(),
+            mint: mint.to_account_info(),
+            to: to.clone(),
+            authority: staking_pool.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)
+    }
+
+    // Emite el pase único de la reserva y retira la autoridad de emisión;
+    // si la propiedad no admite transferencias la cuenta queda congelada
+    pub fn mint_reservation_pass<'info>(
+        pass_mint: AccountInfo<'info>,
+        to: AccountInfo<'info>,
+        reservation: &Pubkey,
+        pass_bump: u8,
+        transferable: bool,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        let seeds = &[
+            b"reservation-pass".as_ref(),
+            reservation.as_ref(),
+            &[pass_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::MintTo {
+            mint: pass_mint.clone(),
+            to: to.clone(),
+            authority: pass_mint.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+        token_interface::mint_to(cpi_ctx, 1)?;
+
+        let cpi_accounts = token_interface::SetAuthority {
+            current_authority: pass_mint.clone(),
+            account_or_mint: pass_mint.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+        token_interface::set_authority(cpi_ctx, AuthorityType::MintTokens, None)?;
+
+        if !transferable {
+            let cpi_accounts = token_interface::FreezeAccount {
+                account: to.clone(),
+                mint: pass_mint.clone(),
+                authority: pass_mint.clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+            token_interface::freeze_account(cpi_ctx)?;
+        }
 
         Ok(())
     }
 
-    // Function to burn derivatives and unlock project tokens
-    pub fn unlock_project_tokens(
-        ctx: Context<TokenUnlock>,
-        timestamp: String,
-        vault_bump: u8,
-        amount: u64
+    // Entrega el pase depositado en una oferta y cierra su cuenta
+    #[allow(clippy::too_many_arguments)]
+    pub fn release_listed_pass<'info>(
+        listing_pass_vault: AccountInfo<'info>,
+        to: AccountInfo<'info>,
+        pass_mint: AccountInfo<'info>,
+        listing: AccountInfo<'info>,
+        destination: AccountInfo<'info>,
+        reservation: &Pubkey,
+        listing_bump: u8,
+        token_program: AccountInfo<'info>,
     ) -> Result<()> {
-        // Validate timestamp and check if unlock time has passed
-        let now_ts = Clock::get()?.unix_timestamp as u64;
-        let date_ts = normalize_timestamp(&timestamp)?;
-        require!(now_ts > date_ts, CustomError::UnlockTimeNotReached);
+        let seeds = &[
+            b"listing".as_ref(),
+            reservation.as_ref(),
+            &[listing_bump],
+        ];
+        let signer = &[&seeds[..]];
 
-        // Burn derivative tokens
-        burn_derivative_tokens(&ctx, &timestamp, amount)?;
+        let cpi_accounts = TransferChecked {
+            from: listing_pass_vault.clone(),
+            mint: pass_mint,
+            to: to.clone(),
+            authority: listing.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, 0)?;
 
-        // Transfer project tokens from vault to user
-        transfer_from_vault(&ctx, &timestamp, vault_bump, amount)?;
+        close_listing_vault(
+            listing_pass_vault,
+            listing,
+            destination,
+            reservation,
+            listing_bump,
+            token_program,
+        )
+    }
 
-        // Emit Token Unlock Event
-        emit!(TokenUnlockEvent {
-            basetoken: ctx.accounts.basemint.key(),
-            amount,
-            derivativetoken: ctx.accounts.mint.key(),
-            receiver: ctx.accounts.base_ata.owner,
-            timestamp: date_ts.to_string(),
-            label: "tokenunlock".to_string()
-        });
+    // Cierra el vault vacío de una oferta y devuelve su renta a `destination`
+    pub fn close_listing_vault<'info>(
+        listing_pass_vault: AccountInfo<'info>,
+        listing: AccountInfo<'info>,
+        destination: AccountInfo<'info>,
+        reservation: &Pubkey,
+        listing_bump: u8,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        let seeds = &[
+            b"listing".as_ref(),
+            reservation.as_ref(),
+            &[listing_bump],
+        ];
+        let signer = &[&seeds[..]];
 
-        Ok(())
+        let cpi_accounts = token_interface::CloseAccount {
+            account: listing_pass_vault,
+            destination,
+            authority: listing,
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)
     }
 
+    // Quema el pase que quede en `holder`, descongelando antes su cuenta, y cierra el
+    // mint del pase; si el mint ya está cerrado no queda nada que hacer
+    pub fn retire_reservation_pass<'info>(
+        pass_mint: AccountInfo<'info>,
+        holder: Option<AccountInfo<'info>>,
+        destination: AccountInfo<'info>,
+        reservation: &Pubkey,
+        pass_bump: u8,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        if pass_mint.data_is_empty() {
+            return Ok(());
+        }
+        let seeds = &[
+            b"reservation-pass".as_ref(),
+            reservation.as_ref(),
+            &[pass_bump],
+        ];
+        let signer = &[&seeds[..]];
 
-    
-    pub fn stake_tokens(
-        user_staking_account: AccountInfo<'info>,
-        admin_staking_account: AccountInfo<'info>,
-        user: AccountInfo<'info>,
-        amount: u64,
+        let supply = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(
+            &pass_mint.try_borrow_data()?,
+        )?
+        .base
+        .supply;
+        if supply > 0 {
+            let holder = holder.ok_or(ErrorCode::NotPassHolder)?;
+            let holder_account = StateWithExtensions::<spl_token_2022::state::Account>::unpack(
+                &holder.try_borrow_data()?,
+            )?
+            .base;
+            require!(
+                holder_account.mint == pass_mint.key() && holder_account.amount == supply,
+                ErrorCode::NotPassHolder
+            );
+
+            // El mint es autoridad de congelación y delegado permanente de sus cuentas
+            if holder_account.is_frozen() {
+                let cpi_accounts = token_interface::ThawAccount {
+                    account: holder.clone(),
+                    mint: pass_mint.clone(),
+                    authority: pass_mint.clone(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+                token_interface::thaw_account(cpi_ctx)?;
+            }
+            let cpi_accounts = token_interface::Burn {
+                mint: pass_mint.clone(),
+                from: holder.clone(),
+                authority: pass_mint.clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+            token_interface::burn(cpi_ctx, supply)?;
+        }
+
+        let cpi_accounts = token_interface::CloseAccount {
+            account: pass_mint.clone(),
+            destination,
+            authority: pass_mint.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)
+    }
+
+    // Cierra la cuenta del pase ya vacía; firma su titular, que recupera la renta
+    pub fn close_pass_account<'info>(
+        pass_account: AccountInfo<'info>,
+        owner: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        let cpi_accounts = token_interface::CloseAccount {
+            account: pass_account,
+            destination: owner.clone(),
+            authority: owner,
+        };
+        token_interface::close_account(CpiContext::new(token_program, cpi_accounts))
+    }
+
+    // Cierra el escrow vacío y devuelve su renta a `destination`
+    pub fn close_escrow<'info>(
+        escrow_vault: AccountInfo<'info>,
+        mint: AccountInfo<'info>,
+        destination: AccountInfo<'info>,
+        reservation: &Pubkey,
+        vault_bump: u8,
         token_program: AccountInfo<'info>,
     ) -> Result<()> {
-        // Transferir tokens del usuario a la cuenta de staking del administrador
-        let cpi_accounts = Transfer {
-            from: user_staking_account.clone(),
-            to: admin_staking_account.clone(),
-            authority: user.clone(),
+        // Token-2022 no deja cerrar una cuenta con comisiones retenidas; se pasan al token
+        let withheld = if *escrow_vault.owner == spl_token_2022::ID {
+            withheld_fees(&escrow_vault.try_borrow_data()?)?
+        } else {
+            0
         };
-        let cpi_program = token_program.clone();
-        token::transfer(CpiContext::new(cpi_program, cpi_accounts), amount)?;
+        if withheld > 0 {
+            let cpi_accounts = transfer_fee::HarvestWithheldTokensToMint {
+                token_program_id: token_program.clone(),
+                mint,
+            };
+            let cpi_ctx = CpiContext::new(token_program.clone(), cpi_accounts);
+            transfer_fee::harvest_withheld_tokens_to_mint(cpi_ctx, vec![escrow_vault.clone()])?;
+        }
 
-        // Colocar los tokens en staking
-        // Implementación de la lógica de staking aquí...
+        let seeds = &[
+            b"reservation-vault".as_ref(),
+            reservation.as_ref(),
+            &[vault_bump],
+        ];
+        let signer = &[&seeds[..]];
 
-        Ok(())
+        let cpi_accounts = token_interface::CloseAccount {
+            account: escrow_vault.clone(),
+            destination: destination.clone(),
+            authority: escrow_vault.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)
     }
 
-    pub fn transfer_from_staking(
-        from: AccountInfo<'info>,
-        to: AccountInfo<'info>,
-        authority: AccountInfo<'info>,
+    // Devuelve `amount` al titular de la reserva o, en reservas de grupo, a cada huésped
+    // en proporción a lo que aportó; `guest_accounts` son sus cuentas de token en el
+    // orden de las partes
+    #[allow(clippy::too_many_arguments)]
+    pub fn refund_guests<'info>(
+        escrow_vault: AccountInfo<'info>,
+        user_account: AccountInfo<'info>,
+        group_booking: Option<&GroupBooking>,
+        guest_accounts: &'info [AccountInfo<'info>],
+        mint: &InterfaceAccount<'info, Mint>,
+        reservation: &Account<'info, Reservation>,
         amount: u64,
         token_program: AccountInfo<'info>,
     ) -> Result<()> {
-        let cpi_accounts = Transfer {
-            from: from.clone(),
-            to: to.clone(),
-            authority: authority.clone(),
-        };
-        let cpi_program = token_program.clone();
-        token::transfer(CpiContext::new(cpi_program, cpi_accounts), amount)?;
+        if amount == 0 {
+            return Ok(());
+        }
+        if !reservation.is_group {
+            return transfer_from_escrow(
+                escrow_vault,
+                user_account,
+                mint,
+                &reservation.key(),
+                reservation.vault_bump,
+                amount,
+                token_program,
+            );
+        }
+
+        let group_booking = group_booking.ok_or(ErrorCode::GroupBookingRequired)?;
+        require!(
+            guest_accounts.len() == group_booking.shares.len(),
+            ErrorCode::InvalidRemainingAccounts
+        );
+        let parts = group_booking.refund_parts(amount)?;
+        for ((share, token_info), part) in group_booking
+            .shares
+            .iter()
+            .zip(guest_accounts.iter())
+            .zip(parts)
+        {
+            let guest_token_account = InterfaceAccount::<TokenAccount>::try_from(token_info)?;
+            require!(
+                guest_token_account.owner == share.guest && guest_token_account.mint == mint.key(),
+                ErrorCode::InvalidRemainingAccounts
+            );
+            if part > 0 {
+                transfer_from_escrow(
+                    escrow_vault.clone(),
+                    token_info.clone(),
+                    mint,
+                    &reservation.key(),
+                    reservation.vault_bump,
+                    part,
+                    token_program.clone(),
+                )?;
+            }
+        }
         Ok(())
     }
 
+    // Comisiones de transferencia retenidas en una cuenta de Token-2022
+    pub fn withheld_fees(data: &[u8]) -> Result<u64> {
+        let account = StateWithExtensions::<spl_token_2022::state::Account>::unpack(data)?;
+        Ok(account
+            .get_extension::<TransferFeeAmount>()
+            .map_or(0, |fee| u64::from(fee.withheld_amount)))
+    }
+
 
 
 
@@ -248,10 +4947,11 @@ fn mint_derivative_tokens<'info>(
     amount: u64
 ) -> Result<()> {
     let data_account = &ctx.accounts.data_account;
+    let basemint = ctx.accounts.basemint.key();
     let seeds = &[
         b"mint-data".as_ref(),
         timestamp.as_ref(),
-        ctx.accounts.basemint.key().as_ref(),
+        basemint.as_ref(),
         &[data_account.bump],
     ];
     let signer = &[&seeds[..]];
@@ -278,10 +4978,11 @@ fn burn_derivative_tokens<'info>(
     amount: u64
 ) -> Result<()> {
     let data_account = &ctx.accounts.data_account;
+    let basemint = ctx.accounts.basemint.key();
     let seeds = &[
         b"mint-data".as_ref(),
         timestamp.as_ref(),
-        ctx.accounts.basemint.key().as_ref(),
+        basemint.as_ref(),
         &[data_account.bump],
     ];
     let signer = &[&seeds[..]];
@@ -305,10 +5006,11 @@ fn transfer_from_vault<'info>(
     vault_bump: u8,
     amount: u64
 ) -> Result<()> {
+    let basemint = ctx.accounts.basemint.key();
     let seeds = &[
         b"mint-vault".as_ref(),
         timestamp.as_ref(),
-        ctx.accounts.basemint.key().as_ref(),
+        basemint.as_ref(),
         &[vault_bump],
     ];
     let signer = &[&seeds[..]];
@@ -337,11 +5039,11 @@ pub struct TokenLock<'info> {
 
     // project token
     #[account(mut)]
-    pub basemint: Account<'info, Mint>,
+    pub basemint: Account<'info, token::Mint>,
 
     // ATA of project token owned by caller
     #[account(mut, constraint = base_ata.mint ==  basemint.key(), constraint = base_ata.owner == user.key())]
-    pub base_ata: Account<'info, TokenAccount>,
+    pub base_ata: Account<'info, token::TokenAccount>,
 
     // Data account
     #[account(
@@ -356,7 +5058,7 @@ pub struct TokenLock<'info> {
         seeds = [b"mint-vault".as_ref(),_timestamp.as_ref(),basemint.key().as_ref()],
         bump=_vault_bump
     )]
-    pub vest_account: Box<Account<'info, TokenAccount>>,
+    pub vest_account: Box<Account<'info, token::TokenAccount>>,
 
     #[account(mut)]
     pub user: Signer<'info>,
@@ -368,11 +5070,11 @@ pub struct TokenLock<'info> {
         bump = data_account.tokenbump,
         constraint = mint.key() == data_account.mintkey
     )]
-    pub mint: Account<'info, Mint>,
+    pub mint: Account<'info, token::Mint>,
 
     // Derivative ATA key is mint key
     #[account(mut, constraint = der_ata.mint ==  mint.key())]
-    pub der_ata: Account<'info, TokenAccount>,
+    pub der_ata: Account<'info, token::TokenAccount>,
 
     pub token_program: Program<'info, Token>,
     
@@ -386,11 +5088,11 @@ pub struct TokenUnlock<'info> {
     
     // project token
     #[account(mut)]
-    pub basemint: Account<'info, Mint>,
+    pub basemint: Account<'info, token::Mint>,
 
     // ATA of project token owned by caller
     #[account(mut, constraint = base_ata.mint ==  basemint.key(), constraint = base_ata.owner == user.key())]
-    pub base_ata: Account<'info, TokenAccount>,
+    pub base_ata: Account<'info, token::TokenAccount>,
 
     // Data account
     #[account(
@@ -405,7 +5107,7 @@ pub struct TokenUnlock<'info> {
         seeds = [b"mint-vault".as_ref(),_timestamp.as_ref(),basemint.key().as_ref()],
         bump=_vault_bump
     )]
-    pub vest_account: Box<Account<'info, TokenAccount>>,
+    pub vest_account: Box<Account<'info, token::TokenAccount>>,
 
     #[account(mut)]
     pub user: Signer<'info>,
@@ -417,11 +5119,11 @@ pub struct TokenUnlock<'info> {
         bump = data_account.tokenbump,
         constraint = mint.key() == data_account.mintkey
     )]
-    pub mint: Account<'info, Mint>,
+    pub mint: Account<'info, token::Mint>,
 
     // Derivative ATA owned by caller
     #[account(mut, constraint = der_ata.mint ==  mint.key(), constraint = der_ata.owner == user.key())]
-    pub der_ata: Account<'info, TokenAccount>,
+    pub der_ata: Account<'info, token::TokenAccount>,
 
     pub token_program: Program<'info, Token>,
     
@@ -429,6 +5131,15 @@ pub struct TokenUnlock<'info> {
     pub rent: Sysvar<'info, Rent>
 }
 
+// Datos del token derivado de cada bloqueo
+#[account]
+#[derive(Default)]
+pub struct MintdAccount {
+    pub mintkey: Pubkey,
+    pub tokenbump: u8,
+    pub bump: u8,
+}
+
 // Event definitions
 #[event]
 pub struct TokenLockEvent {
@@ -452,6 +5163,270 @@ pub struct TokenUnlockEvent {
     pub label: String,
 }
 
+#[event]
+pub struct DisputeOpenedEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub opened_by: Pubkey,
+    pub reason_hash: [u8; 32],
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct DisputeResolvedEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub arbiter: Pubkey,
+    pub guest_amount: u64,
+    pub owner_amount: u64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct ReservationCreatedEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub deposit: u64,
+    pub check_in_date: i64,
+    pub check_out_date: i64,
+    pub status: ReservationStatus,
+    pub referrer: Pubkey,
+    pub guests: u8,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct ReservationApprovedEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest: Pubkey,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct ReservationRejectedEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest: Pubkey,
+    pub refund: u64,
+    pub status: ReservationStatus,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct ReservationModifiedEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest: Pubkey,
+    pub old_amount: u64,
+    pub new_amount: u64,
+    pub old_check_in_date: i64,
+    pub old_check_out_date: i64,
+    pub new_check_in_date: i64,
+    pub new_check_out_date: i64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct ReservationCancelledEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest: Pubkey,
+    pub refund: u64,
+    pub penalty: u64,
+    pub deposit_refund: u64,
+    pub cancelled_at: i64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct CheckedInEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest: Pubkey,
+    pub released_amount: u64,
+    pub checked_in_at: i64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct CheckedOutEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest: Pubkey,
+    pub released_amount: u64,
+    pub checked_out_at: i64,
+    pub confirmed_by: Pubkey,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct NoShowSettledEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest: Pubkey,
+    pub owner_payout: u64,
+    pub refund: u64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct DamagesClaimedEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest: Pubkey,
+    pub amount: u64,
+    pub evidence_hash: [u8; 32],
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct DepositReleasedEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest: Pubkey,
+    pub damages: u64,
+    pub refund: u64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct YieldDistributedEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub guest_share: u64,
+    pub owner_share: u64,
+    pub platform_share: u64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct GroupContributionEvent {
+    pub reservation: Pubkey,
+    pub guest: Pubkey,
+    pub amount: u64,
+    pub funded_amount: u64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct GroupBookingFundedEvent {
+    pub reservation: Pubkey,
+    pub organizer: Pubkey,
+    pub funded_amount: u64,
+    pub status: ReservationStatus,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct ReservationListedEvent {
+    pub reservation: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct AcceptedMintRemovedEvent {
+    pub property: Pubkey,
+    pub mint: Pubkey,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct ListingCancelledEvent {
+    pub reservation: Pubkey,
+    pub seller: Pubkey,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct ReservationResoldEvent {
+    pub reservation: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub royalty: u64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct PayoutReleasedEvent {
+    pub reservation: Pubkey,
+    pub property: Pubkey,
+    pub owner_amount: u64,
+    pub delegate_share: u64,
+    pub platform_fee: u64,
+    pub referrer: Pubkey,
+    pub referral_fee: u64,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct RoleGrantedEvent {
+    pub property: Pubkey,
+    pub member: Pubkey,
+    pub role: Role,
+    pub can_approve_bookings: bool,
+    pub can_block_dates: bool,
+    pub payout_share_bps: u16,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct RoleRevokedEvent {
+    pub property: Pubkey,
+    pub member: Pubkey,
+    pub role: Role,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct DatesBlockedEvent {
+    pub property: Pubkey,
+    pub start_date: i64,
+    pub end_date: i64,
+    pub reason: BlockReason,
+    pub blocked_by: Pubkey,
+    #[index]
+    pub label: String,
+}
+
+#[event]
+pub struct DatesUnblockedEvent {
+    pub property: Pubkey,
+    pub start_date: i64,
+    pub end_date: i64,
+    pub unblocked_by: Pubkey,
+    #[index]
+    pub label: String,
+}
+
 // Custom error definitions
 #[error_code]
 pub enum CustomError {
@@ -463,3 +5438,270 @@ pub enum CustomError {
     UnlockTimeNotReached,
     // ... (otros errores existentes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::program_pack::Pack;
+    use anchor_spl::token_2022::spl_token_2022::{
+        extension::{
+            transfer_fee::TransferFeeAmount, BaseStateWithExtensionsMut, ExtensionType,
+            StateWithExtensionsMut,
+        },
+        state::{Account, AccountState},
+    };
+
+    fn initialized_account() -> Account {
+        Account {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 0,
+            state: AccountState::Initialized,
+            ..Account::default()
+        }
+    }
+
+    #[test]
+    fn withheld_fees_reads_fee_mint_account() {
+        let len = ExtensionType::try_calculate_account_len::<Account>(&[
+            ExtensionType::TransferFeeAmount,
+        ])
+        .unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<Account>::unpack_uninitialized(&mut data).unwrap();
+        state
+            .init_extension::<TransferFeeAmount>(true)
+            .unwrap()
+            .withheld_amount = 25.into();
+        state.base = initialized_account();
+        state.pack_base();
+        state.init_account_type().unwrap();
+
+        assert_eq!(admin::withheld_fees(&data).unwrap(), 25);
+    }
+
+    #[test]
+    fn withheld_fees_is_zero_without_fee_extension() {
+        let mut data = vec![0; Account::LEN];
+        Account::pack(initialized_account(), &mut data).unwrap();
+
+        assert_eq!(admin::withheld_fees(&data).unwrap(), 0);
+    }
+
+    // Cuenta PriceUpdateV2 tal como la escribe el receptor de Pyth
+    fn price_update(verification_level: &[u8], feed_id: [u8; 32], price: i64) -> Vec<u8> {
+        let mut data = oracle::PRICE_UPDATE_V2_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(verification_level);
+        data.extend_from_slice(&feed_id);
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&5u64.to_le_bytes());
+        data.extend_from_slice(&(-8i32).to_le_bytes());
+        data.extend_from_slice(&1_700_000_000i64.to_le_bytes());
+        data.extend_from_slice(&1_699_999_999i64.to_le_bytes());
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&5u64.to_le_bytes());
+        data.extend_from_slice(&42u64.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn parse_price_update_reads_verified_feed() {
+        let price = oracle::parse_price_update(&price_update(&[1], [7; 32], 100_000_000), &[7; 32])
+            .unwrap();
+
+        assert_eq!(price.price, 100_000_000);
+        assert_eq!(price.expo, -8);
+        assert_eq!(price.conf, 5);
+        assert_eq!(price.publish_time, 1_700_000_000);
+    }
+
+    #[test]
+    fn parse_price_update_rejects_other_feed_or_partial_verification() {
+        assert!(oracle::parse_price_update(&price_update(&[1], [7; 32], 1), &[8; 32]).is_err());
+        assert!(oracle::parse_price_update(&price_update(&[0, 3], [7; 32], 1), &[7; 32]).is_err());
+    }
+
+    fn accepted_mint(decimals: u8) -> AcceptedMint {
+        AcceptedMint {
+            property: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            decimals,
+            price_feed: Pubkey::new_unique(),
+            feed_id: [7; 32],
+            payout_account: Pubkey::new_unique(),
+            max_price_age: 60,
+            max_confidence_bps: 100,
+            bump: 255,
+        }
+    }
+
+    fn oracle_price(price: i64, expo: i32, conf: u64) -> oracle::OraclePrice {
+        oracle::OraclePrice {
+            price,
+            expo,
+            conf,
+            publish_time: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn to_tokens_scales_by_positive_exponent() {
+        // 1,50 por token de 6 decimales y presupuesto en céntimos: 6 - 2 + 8 = 12
+        let price = oracle_price(150_000_000, -8, 0);
+        let tokens = accepted_mint(6).to_tokens(&price, 300, 2, 1_700_000_000).unwrap();
+        assert_eq!(tokens, 2_000_000);
+    }
+
+    #[test]
+    fn to_tokens_scales_by_negative_exponent() {
+        // 300 por token sin decimales y presupuesto con 6 decimales: 0 - 6 - 2 = -8
+        let price = oracle_price(3, 2, 0);
+        let mint = accepted_mint(0);
+        assert_eq!(mint.to_tokens(&price, 600_000_000, 6, 1_700_000_000).unwrap(), 2);
+        assert_eq!(mint.to_tokens(&price, 1_000_000_000, 6, 1_700_000_000).unwrap(), 4);
+    }
+
+    #[test]
+    fn to_tokens_rounds_up_for_the_owner() {
+        let price = oracle_price(150_000_000, -8, 0);
+        let tokens = accepted_mint(6).to_tokens(&price, 100, 2, 1_700_000_000).unwrap();
+        assert_eq!(tokens, 666_667);
+    }
+
+    #[test]
+    fn to_tokens_rejects_stale_price() {
+        let price = oracle_price(150_000_000, -8, 0);
+        let mint = accepted_mint(6);
+        assert!(mint.to_tokens(&price, 300, 2, 1_700_000_060).is_ok());
+        assert_eq!(
+            mint.to_tokens(&price, 300, 2, 1_700_000_061).unwrap_err(),
+            ErrorCode::StalePrice.into()
+        );
+    }
+
+    #[test]
+    fn to_tokens_enforces_confidence_limit() {
+        // Con un 1% de margen el intervalo puede llegar a 1.500.000
+        let mint = accepted_mint(6);
+        let within = oracle_price(150_000_000, -8, 1_500_000);
+        let beyond = oracle_price(150_000_000, -8, 1_500_001);
+        assert!(mint.to_tokens(&within, 300, 2, 1_700_000_000).is_ok());
+        assert_eq!(
+            mint.to_tokens(&beyond, 300, 2, 1_700_000_000).unwrap_err(),
+            ErrorCode::PriceConfidenceTooLow.into()
+        );
+    }
+
+    fn group_booking(received: &[u64]) -> GroupBooking {
+        GroupBooking {
+            reservation: Pubkey::new_unique(),
+            organizer: Pubkey::new_unique(),
+            funding_deadline: 0,
+            funded_amount: received.iter().sum(),
+            shares: received
+                .iter()
+                .map(|&received| GuestShare {
+                    guest: Pubkey::new_unique(),
+                    amount: received,
+                    paid: true,
+                    received,
+                })
+                .collect(),
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn refund_parts_follow_contributions() {
+        let group = group_booking(&[500, 300, 200]);
+        assert_eq!(group.refund_parts(1_000).unwrap(), vec![500, 300, 200]);
+        assert_eq!(group.refund_parts(100).unwrap(), vec![50, 30, 20]);
+    }
+
+    #[test]
+    fn refund_parts_give_rounding_remainder_to_last_guest() {
+        let group = group_booking(&[1, 1, 1]);
+        let parts = group.refund_parts(100).unwrap();
+        assert_eq!(parts, vec![33, 33, 34]);
+        assert_eq!(parts.iter().sum::<u64>(), 100);
+    }
+
+    // 2024-01-01 fue lunes
+    const MONDAY: i64 = 19723 * calendar::SECONDS_PER_DAY;
+
+    fn rate_schedule(
+        weekday_multipliers_bps: [u16; 7],
+        overrides: Vec<RateOverride>,
+        stay_discounts: Vec<StayDiscount>,
+    ) -> RateSchedule {
+        RateSchedule {
+            owner: Pubkey::new_unique(),
+            property: Pubkey::new_unique(),
+            weekday_multipliers_bps,
+            overrides,
+            stay_discounts,
+            bump: 255,
+        }
+    }
+
+    fn night(day: i64) -> i64 {
+        MONDAY + day * calendar::SECONDS_PER_DAY
+    }
+
+    #[test]
+    fn stay_price_applies_overrides_from_start_to_end_exclusive() {
+        // Tarifa especial las noches del martes y el miércoles
+        let schedule = rate_schedule(
+            [10_000; 7],
+            vec![RateOverride { start_date: night(1), end_date: night(3), nightly_rate: 200 }],
+            vec![],
+        );
+        assert_eq!(schedule.stay_price(100, night(0), night(4)).unwrap(), 600);
+        assert_eq!(schedule.stay_price(100, night(3), night(4)).unwrap(), 100);
+        assert_eq!(schedule.stay_price(100, night(2), night(3)).unwrap(), 200);
+    }
+
+    #[test]
+    fn stay_price_applies_weekday_multipliers() {
+        // Viernes y sábado un 50% más caros
+        let schedule = rate_schedule(
+            [10_000, 10_000, 10_000, 10_000, 15_000, 15_000, 10_000],
+            vec![],
+            vec![],
+        );
+        assert_eq!(schedule.stay_price(100, night(3), night(6)).unwrap(), 400);
+        // La hora del check-in no cambia la noche a la que se aplica
+        assert_eq!(schedule.stay_price(100, night(4) + 15 * 3600, night(5) + 11 * 3600).unwrap(), 150);
+    }
+
+    #[test]
+    fn stay_price_picks_largest_reached_stay_discount() {
+        let schedule = rate_schedule(
+            [10_000; 7],
+            vec![],
+            vec![
+                StayDiscount { min_nights: 3, discount_bps: 500 },
+                StayDiscount { min_nights: 7, discount_bps: 1_000 },
+            ],
+        );
+        assert_eq!(schedule.stay_price(100, night(0), night(2)).unwrap(), 200);
+        assert_eq!(schedule.stay_price(100, night(0), night(3)).unwrap(), 285);
+        assert_eq!(schedule.stay_price(100, night(0), night(6)).unwrap(), 570);
+        assert_eq!(schedule.stay_price(100, night(0), night(7)).unwrap(), 630);
+    }
+
+    #[test]
+    fn validate_referrer_requires_approval() {
+        let guest = Pubkey::new_unique();
+        let referrer = Pubkey::new_unique();
+        let approved = ApprovedReferrer { referrer, bump: 255 };
+        let self_approved = ApprovedReferrer { referrer: guest, bump: 255 };
+
+        assert_eq!(validate_referrer(None, None, &guest).unwrap(), Pubkey::default());
+        assert_eq!(validate_referrer(Some(referrer), Some(&approved), &guest).unwrap(), referrer);
+        assert!(validate_referrer(Some(referrer), None, &guest).is_err());
+        assert!(validate_referrer(Some(guest), Some(&approved), &guest).is_err());
+        assert!(validate_referrer(Some(guest), Some(&self_approved), &guest).is_err());
+    }
+}